@@ -0,0 +1,233 @@
+// The page side of the IPC, as `window.bevy`; see `src/ipc/frame.rs` for how frames look.
+// `window.__bevy` holds the platform's transport and the entry points Rust calls.
+(function() {
+    const internal = window.__bevy;
+    const codec = internal.codecs[internal.codec];
+    // Listeners of messages by `type`, and of the page's own lifecycle, which messages can't fire
+    const listeners = new Map();
+    const lifecycle = new Map([["ready", new Set()]]);
+    const calls = new Map();
+    const topics = new Map();
+    let nextCall = 0;
+    let isReady = false;
+    let resolveReady;
+    const ready = new Promise((resolve) => resolveReady = resolve);
+    // Messages and publishes that arrived before the page was ready, delivered once it is
+    const held = [];
+    // Tells this document apart from the ones the page showed before, so Bevy resets its state
+    // when a new document replaced the old one, but not when a load was cancelled or downloaded
+    const documentId = Date.now().toString(36) + "-" + Math.random().toString(36).slice(2);
+
+    function encodeFrame(header, payload) {
+        const head = new TextEncoder().encode(JSON.stringify(header) + "\n");
+        const body = codec.encode(payload === undefined ? null : payload);
+        const frame = new Uint8Array(head.length + body.length);
+        frame.set(head);
+        frame.set(body, head.length);
+        return frame;
+    }
+
+    function decodeFrame(frame) {
+        const split = frame.indexOf(10);
+        return {
+            header: JSON.parse(new TextDecoder().decode(frame.slice(0, split))),
+            payload: codec.decode(frame.slice(split + 1)),
+        };
+    }
+
+    function send(frame) {
+        return Promise.resolve()
+            .then(() => internal.transport.send(frame))
+            .then((res) => {
+                if (res && res.ok === false) {
+                    throw new Error("Bevy answered with status " + res.status);
+                }
+            });
+    }
+
+    // Sent before any other frame, so Bevy has reset for this document by the time they arrive
+    const loaded = send(encodeFrame({ kind: "load", document: documentId })).catch(console.error);
+
+    // Resolves once the frame is handed to Bevy, whatever the platform's transport does
+    function post(header, payload) {
+        const frame = encodeFrame(header, payload);
+        return loaded.then(() => send(frame));
+    }
+
+    // Calls each callback, so one that throws doesn't keep the message from the others
+    function notify(callbacks, payload) {
+        (callbacks || []).forEach((callback) => {
+            try {
+                callback(payload);
+            } catch (error) {
+                console.error(error);
+            }
+        });
+    }
+
+    function emit(type, payload) {
+        notify(listeners.get(type), payload);
+    }
+
+    class InvokeError extends Error {
+        constructor(command, data) {
+            super(typeof data === "string" ? data : command + " failed: " + JSON.stringify(data));
+            this.name = "InvokeError";
+            this.command = command;
+            this.data = data;
+        }
+    }
+
+    function dispatch(header, payload) {
+        if (header.kind === "message") {
+            emit("message", payload);
+            if (payload && typeof payload.type === "string" && payload.type !== "message") {
+                emit(payload.type, payload);
+            }
+            window.processMessage(payload);
+        } else if (header.kind === "reply" && calls.has(header.id)) {
+            const call = calls.get(header.id);
+            calls.delete(header.id);
+            header.ok ? call.resolve(payload) : call.reject(new InvokeError(call.command, payload));
+        } else if (header.kind === "publish" && topics.has(header.topic)) {
+            notify(topics.get(header.topic), payload);
+        }
+    }
+
+    internal.receive = function(frame) {
+        const { header, payload } = decodeFrame(frame);
+        // Bevy may deliver frames meant for the document before, until this one announced itself;
+        // replies are let through, as the page may wait for calls before it's ready
+        if (!isReady && header.kind !== "reply") {
+            held.push({ header, payload });
+        } else {
+            dispatch(header, payload);
+        }
+    };
+
+    const bevy = {
+        InvokeError,
+
+        // Whether the page is done loading, and listeners added while it loaded are in place
+        get isReady() {
+            return isReady;
+        },
+
+        ready,
+
+        // Set to `false` before the DOM is loaded, e.g. at the top of a page script, to announce
+        // readiness with `markReady` instead, once the page's listeners are actually in place
+        autoReady: true,
+
+        // Tells Bevy the page is listening, so the messages it held back are delivered
+        markReady() {
+            if (isReady) {
+                return;
+            }
+            isReady = true;
+            resolveReady();
+            notify(lifecycle.get("ready"));
+            held.splice(0).forEach(({ header, payload }) => dispatch(header, payload));
+            post({ kind: "ready", document: documentId }).catch(console.error);
+        },
+
+        // Calls `callback` with every message from Bevy for `type` "message", with messages whose
+        // `type` property is `type` (as with `#[serde(tag = "type")]`), or once ready for "ready";
+        // messages whose `type` is "message" or "ready" only reach "message" listeners
+        on(type, callback) {
+            if (lifecycle.has(type)) {
+                lifecycle.get(type).add(callback);
+                if (type === "ready" && isReady) {
+                    callback();
+                }
+                return () => bevy.off(type, callback);
+            }
+            if (!listeners.has(type)) {
+                listeners.set(type, new Set());
+            }
+            listeners.get(type).add(callback);
+            return () => bevy.off(type, callback);
+        },
+
+        off(type, callback) {
+            if (lifecycle.has(type)) {
+                lifecycle.get(type).delete(callback);
+                return;
+            }
+            const callbacks = listeners.get(type);
+            if (callbacks && callbacks.delete(callback) && callbacks.size === 0) {
+                listeners.delete(type);
+            }
+        },
+
+        // Sends `msg` to Bevy's `IpcQueue`; rejects if it couldn't be handed over
+        send(msg) {
+            return post({ kind: "message" }, msg);
+        },
+
+        // Calls the handler added with `add_webview_handler(command, ...)`; rejects with an
+        // `InvokeError` if it fails or doesn't answer within `options.timeout` ms (`null` waits forever)
+        invoke(command, args, options) {
+            const id = nextCall++;
+            const timeout = options && options.timeout !== undefined ? options.timeout : 10000;
+            return new Promise((resolve, reject) => {
+                const timer = timeout === null ? null : setTimeout(() => {
+                    calls.delete(id);
+                    reject(new InvokeError(command, command + " timed out after " + timeout + "ms"));
+                }, timeout);
+                calls.set(id, {
+                    command,
+                    resolve: (x) => (clearTimeout(timer), resolve(x)),
+                    reject: (x) => (clearTimeout(timer), reject(x)),
+                });
+
+                post({ kind: "invoke", id, name: command }, args).catch((error) => {
+                    calls.delete(id);
+                    clearTimeout(timer);
+                    reject(new InvokeError(command, error.message));
+                });
+            });
+        },
+
+        // Calls `callback` with every message Bevy publishes to `topic`; returns a function that
+        // undoes the subscription
+        subscribe(topic, callback) {
+            if (!topics.has(topic)) {
+                topics.set(topic, new Set());
+                post({ kind: "subscribe", topic }).catch(console.error);
+            }
+            topics.get(topic).add(callback);
+            return () => bevy.unsubscribe(topic, callback);
+        },
+
+        unsubscribe(topic, callback) {
+            const callbacks = topics.get(topic);
+            if (callbacks && callbacks.delete(callback) && callbacks.size === 0) {
+                topics.delete(topic);
+                post({ kind: "unsubscribe", topic }).catch(console.error);
+            }
+        },
+    };
+
+    // Scripts of the page, including modules, have run by the time the DOM is loaded
+    // Bevy holds its messages until the page says it's listening, so none arrive before the
+    // page's own scripts had a chance to call `bevy.on`; pages that set up their listeners later
+    // turn `autoReady` off
+    function onLoaded() {
+        if (bevy.autoReady) {
+            bevy.markReady();
+        }
+    }
+
+    if (document.readyState === "loading") {
+        document.addEventListener("DOMContentLoaded", onLoaded, { once: true });
+    } else {
+        onLoaded();
+    }
+
+    window.bevy = bevy;
+
+    // The single listener and fire-and-forget send of earlier versions
+    window.processMessage = function(item) {};
+    window.sendMessage = (msg) => bevy.send(msg).catch((error) => console.error("Send error: " + error.message));
+})();
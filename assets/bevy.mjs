@@ -0,0 +1,15 @@
+// `window.bevy` as an ES module, for UIs built with a bundler: `import bevy from "./bevy.mjs"`
+//
+// The client itself is injected into every page by `WebViewPlugin`; this only hands it out.
+const bevy = window.bevy;
+
+export default bevy;
+export const InvokeError = bevy.InvokeError;
+export const ready = bevy.ready;
+export const markReady = bevy.markReady;
+export const on = bevy.on;
+export const off = bevy.off;
+export const send = bevy.send;
+export const invoke = bevy.invoke;
+export const subscribe = bevy.subscribe;
+export const unsubscribe = bevy.unsubscribe;
@@ -0,0 +1,223 @@
+// Minimal CBOR (RFC 8949) encoder/decoder covering what serde/ciborium produce
+window.CBOR = (function() {
+    function encode(value) {
+        let bytes = new Uint8Array(256);
+        let length = 0;
+
+        function reserve(n) {
+            if (length + n > bytes.length) {
+                const grown = new Uint8Array(Math.max(bytes.length * 2, length + n));
+                grown.set(bytes);
+                bytes = grown;
+            }
+        }
+
+        function pushByte(b) {
+            reserve(1);
+            bytes[length++] = b;
+        }
+
+        function pushBytes(b) {
+            reserve(b.length);
+            bytes.set(b, length);
+            length += b.length;
+        }
+
+        function head(major, n) {
+            if (n < 24) {
+                pushByte(major << 5 | n);
+            } else if (n < 0x100) {
+                pushByte(major << 5 | 24);
+                pushByte(n);
+            } else if (n < 0x10000) {
+                pushByte(major << 5 | 25);
+                pushByte(n >>> 8);
+                pushByte(n & 0xff);
+            } else if (n < 0x100000000) {
+                pushByte(major << 5 | 26);
+                pushBytes([n >>> 24, n >>> 16 & 0xff, n >>> 8 & 0xff, n & 0xff]);
+            } else {
+                pushByte(major << 5 | 27);
+                const high = Math.floor(n / 0x100000000);
+                const low = n % 0x100000000;
+                pushBytes([
+                    high >>> 24, high >>> 16 & 0xff, high >>> 8 & 0xff, high & 0xff,
+                    low >>> 24, low >>> 16 & 0xff, low >>> 8 & 0xff, low & 0xff,
+                ]);
+            }
+        }
+
+        function write(v) {
+            if (v === false) {
+                pushByte(0xf4);
+            } else if (v === true) {
+                pushByte(0xf5);
+            } else if (v === null) {
+                pushByte(0xf6);
+            } else if (v === undefined) {
+                pushByte(0xf7);
+            } else if (typeof v === "number") {
+                if (Number.isSafeInteger(v)) {
+                    v >= 0 ? head(0, v) : head(1, -1 - v);
+                } else {
+                    const view = new DataView(new ArrayBuffer(8));
+                    view.setFloat64(0, v);
+                    pushByte(0xfb);
+                    pushBytes(new Uint8Array(view.buffer));
+                }
+            } else if (typeof v === "bigint") {
+                v >= 0n ? head(0, Number(v)) : head(1, Number(-1n - v));
+            } else if (typeof v === "string") {
+                const utf8 = new TextEncoder().encode(v);
+                head(3, utf8.length);
+                pushBytes(utf8);
+            } else if (v instanceof Uint8Array) {
+                head(2, v.length);
+                pushBytes(v);
+            } else if (v instanceof ArrayBuffer) {
+                write(new Uint8Array(v));
+            } else if (Array.isArray(v)) {
+                head(4, v.length);
+                v.forEach(write);
+            } else if (v instanceof Map) {
+                head(5, v.size);
+                v.forEach((value, key) => {
+                    write(key);
+                    write(value);
+                });
+            } else if (typeof v === "object") {
+                const keys = Object.keys(v).filter((key) => v[key] !== undefined);
+                head(5, keys.length);
+                keys.forEach((key) => {
+                    write(key);
+                    write(v[key]);
+                });
+            } else {
+                throw new Error("Cannot CBOR encode " + typeof v);
+            }
+        }
+
+        write(value);
+        return bytes.slice(0, length);
+    }
+
+    function decode(input) {
+        const bytes = input instanceof Uint8Array ? input : new Uint8Array(input);
+        const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+        let offset = 0;
+
+        function float16(half) {
+            const exponent = half >>> 10 & 0x1f;
+            const fraction = half & 0x3ff;
+            const sign = half & 0x8000 ? -1 : 1;
+            if (exponent === 0) {
+                return sign * Math.pow(2, -14) * (fraction / 1024);
+            } else if (exponent === 0x1f) {
+                return fraction ? NaN : sign * Infinity;
+            }
+            return sign * Math.pow(2, exponent - 15) * (1 + fraction / 1024);
+        }
+
+        function argument(info) {
+            if (info < 24) {
+                return info;
+            } else if (info === 24) {
+                return view.getUint8(offset++);
+            } else if (info === 25) {
+                offset += 2;
+                return view.getUint16(offset - 2);
+            } else if (info === 26) {
+                offset += 4;
+                return view.getUint32(offset - 4);
+            } else if (info === 27) {
+                offset += 8;
+                return view.getUint32(offset - 8) * 0x100000000 + view.getUint32(offset - 4);
+            } else if (info === 31) {
+                return -1;
+            }
+            throw new Error("Invalid CBOR length");
+        }
+
+        function chunks(major, n, read) {
+            if (n >= 0) {
+                return read(n);
+            }
+            // Indefinite length: definite chunks until the break byte
+            const parts = [];
+            while (bytes[offset] !== 0xff) {
+                const initial = bytes[offset++];
+                if (initial >> 5 !== major) {
+                    throw new Error("Invalid CBOR chunk");
+                }
+                parts.push(read(argument(initial & 0x1f)));
+            }
+            offset++;
+            return parts;
+        }
+
+        function readBytes(n) {
+            offset += n;
+            return bytes.slice(offset - n, offset);
+        }
+
+        function read() {
+            const initial = bytes[offset++];
+            const major = initial >> 5;
+            const info = initial & 0x1f;
+
+            if (major === 7) {
+                switch (info) {
+                    case 20: return false;
+                    case 21: return true;
+                    case 22: return null;
+                    case 23: return undefined;
+                    case 25: offset += 2; return float16(view.getUint16(offset - 2));
+                    case 26: offset += 4; return view.getFloat32(offset - 4);
+                    case 27: offset += 8; return view.getFloat64(offset - 8);
+                    default: return info < 24 ? info : argument(info);
+                }
+            }
+
+            const n = argument(info);
+            switch (major) {
+                case 0: return n;
+                case 1: return -1 - n;
+                case 2: {
+                    const parts = chunks(2, n, readBytes);
+                    if (!Array.isArray(parts)) {
+                        return parts;
+                    }
+                    const joined = new Uint8Array(parts.reduce((sum, x) => sum + x.length, 0));
+                    parts.reduce((at, x) => (joined.set(x, at), at + x.length), 0);
+                    return joined;
+                }
+                case 3: {
+                    const parts = chunks(3, n, (x) => new TextDecoder().decode(readBytes(x)));
+                    return Array.isArray(parts) ? parts.join("") : parts;
+                }
+                case 4: {
+                    const array = [];
+                    for (let i = 0; n < 0 ? bytes[offset] !== 0xff : i < n; i++) {
+                        array.push(read());
+                    }
+                    n < 0 && offset++;
+                    return array;
+                }
+                case 5: {
+                    const object = {};
+                    for (let i = 0; n < 0 ? bytes[offset] !== 0xff : i < n; i++) {
+                        const key = read();
+                        object[key] = read();
+                    }
+                    n < 0 && offset++;
+                    return object;
+                }
+                case 6: return read();
+            }
+        }
+
+        return read();
+    }
+
+    return { encode, decode };
+})();
@@ -0,0 +1,18 @@
+// Page side of `IpcCodec`; `window.__bevy.codec` names the one picked for this webview
+window.__bevy.codecs = {
+    json: {
+        binary: false,
+        encode: (msg) => new TextEncoder().encode(JSON.stringify(msg)),
+        decode: (bytes) => JSON.parse(new TextDecoder().decode(bytes)),
+    },
+    msgpack: {
+        binary: true,
+        encode: (msg) => msgpack.encode(msg),
+        decode: (bytes) => msgpack.decode(bytes),
+    },
+    cbor: {
+        binary: true,
+        encode: (msg) => CBOR.encode(msg),
+        decode: (bytes) => CBOR.decode(bytes),
+    },
+};
@@ -1,26 +1,51 @@
-window.fetchMessage = function() {
-    const req = new XMLHttpRequest();
-    req.responseType = "arraybuffer";
-    req.open("GET", isWindows ? "http://bevy.send" : "bevy://fetch", false);
+// Carries IPC frames over the `bevy://` protocol
+(function() {
+    const internal = window.__bevy;
+    const url = (path) => internal.isWindows ? "http://bevy." + path : "bevy://" + path;
 
-    req.onload = function () {
-        const blob = new Uint8Array(req.response);
-        window.processMessage(msgpack.decode(blob));
-    };
+    // Calls while a fetch is running make it fetch again once done, so frames are received in
+    // order and none are left behind
+    let fetching = false;
+    let fetchAgain = false;
 
-    req.send();
-}
+    internal.transport = {
+        send: function(frame) {
+            return fetch(url("send"), {
+                method: 'POST',
+                body: frame
+            });
+        },
+    };
 
-window.processMessage = function(item) {}
+    // Fetches every frame queued for the page
+    internal.fetch = function() {
+        if (fetching) {
+            fetchAgain = true;
+            return;
+        }
+        fetching = true;
 
-window.sendMessage = async function(msg) {
-    const url = isWindows ? "http://bevy.send" : "bevy://send";
-    try {
-        await fetch(url, {
-            method: 'POST',
-            body: msgpack.encode(msg)
-        });
-    } catch (error) {
-        console.error("Send error: " + error.message);
-    }
-}
+        fetch(url("fetch"))
+            .then((res) => res.arrayBuffer())
+            .then((buffer) => {
+                // Frames come length-prefixed, see `frame::batch`
+                const view = new DataView(buffer);
+                for (let at = 0; at + 4 <= buffer.byteLength; at += 4 + view.getUint32(at)) {
+                    const frame = new Uint8Array(buffer, at + 4, view.getUint32(at));
+                    try {
+                        internal.receive(frame.slice());
+                    } catch (error) {
+                        console.error("Receive error: " + error.message);
+                    }
+                }
+            })
+            .catch((error) => console.error("Fetch error: " + error.message))
+            .finally(() => {
+                fetching = false;
+                if (fetchAgain) {
+                    fetchAgain = false;
+                    internal.fetch();
+                }
+            });
+    };
+})();
@@ -1,10 +1,33 @@
-window.fetchMessage = function(item) {
-    const obj = JSON.parse(item);
-    window.processMessage(obj)
-}
+// Carries IPC frames over `window.ipc` and `evaluate_script`
+(function() {
+    const internal = window.__bevy;
+    const codec = internal.codecs[internal.codec];
 
-window.processMessage = function(item) {}
+    // Only strings cross the Linux IPC; frames of binary codecs travel as base64
+    function bytesToBase64(bytes) {
+        let binary = "";
+        for (let i = 0; i < bytes.length; i += 0x8000) {
+            binary += String.fromCharCode.apply(null, bytes.subarray(i, i + 0x8000));
+        }
+        return btoa(binary);
+    }
 
-window.sendMessage = function(msg) {
-    window.ipc.postMessage(JSON.stringify(msg))
-}
+    function base64ToBytes(text) {
+        return Uint8Array.from(atob(text), (c) => c.charCodeAt(0));
+    }
+
+    internal.transport = {
+        send: function(frame) {
+            window.ipc.postMessage(codec.binary ? bytesToBase64(frame) : new TextDecoder().decode(frame));
+        },
+    };
+
+    // Receives a frame pushed by Rust
+    internal.fetch = function(item) {
+        try {
+            internal.receive(codec.binary ? base64ToBytes(item) : new TextEncoder().encode(item));
+        } catch (error) {
+            console.error("Receive error: " + error.message);
+        }
+    };
+})();
@@ -1,22 +1,43 @@
 use bevy::prelude::*;
 use bevy_simple_text_input::{TextInput, TextInputPlugin, TextInputSubmitEvent};
 use bevy_wry_webview::{
-    ipc::{FetchEvent, IpcQueue, IpcSender},
-    UiWebViewBundle, WebViewHandle, WebViewLocation, WebViewMarker, WebViewPlugin,
+    ipc::{
+        rpc::WebViewRpcExt,
+        typescript::{declarations, TypeScript},
+        WebViewIpc, WebViewMessage, WebViewMessagePlugin,
+    },
+    UiWebViewBundle, WebViewLocation, WebViewMarker, WebViewPlugin,
 };
 use serde::Deserialize;
 
 fn main() {
+    // Types for the page's scripts, e.g. `cargo run --example cube -- --typescript > cube.d.ts`
+    if std::env::args().any(|x| x == "--typescript") {
+        print!("{}", declarations::<String, Msg>());
+        return;
+    }
+
     App::new()
         .add_plugins(DefaultPlugins)
         .add_plugins(WebViewPlugin)
+        .add_plugins(WebViewMessagePlugin::<Msg>::default())
         .add_plugins(TextInputPlugin)
+        .add_webview_handler("greet", |name: String| {
+            if name.is_empty() {
+                Err("nobody to greet")
+            } else {
+                Ok(format!("Hello, {name}!"))
+            }
+        })
+        .add_webview_command("elapsed", |In(()): In<()>, time: Res<Time>| {
+            time.elapsed_seconds()
+        })
         .add_systems(Startup, setup)
         .add_systems(Update, (moving_webview, log_msgs, text_listener))
         .run();
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, TypeScript, Debug)]
 #[serde(tag = "type")]
 enum Msg {
     Count { name: String, count: u16 },
@@ -71,8 +92,13 @@ fn setup(
     <head>
         <script>
 var clickCount = 0;
-addEventListener("click", (event) => {window.sendMessage({ type: clickCount % 2 == 0 ? 'Count' : 'OtherCount', name: 'cube', count: clickCount++ })});
-window.processMessage = (item) => { document.getElementById('inner-ele').innerText = item; }
+const show = (text) => { document.getElementById('inner-ele').innerText = text; };
+addEventListener("click", (event) => {window.bevy.send({ type: clickCount % 2 == 0 ? 'Count' : 'OtherCount', name: 'cube', count: clickCount++ })});
+window.bevy.on("message", show);
+window.bevy.on("ready", async () => {
+    show(await window.bevy.invoke('greet', 'cube'));
+    console.log("Game running for " + await window.bevy.invoke('elapsed') + "s");
+});
         </script>
         <meta charset="UTF-8">
         <meta name="viewport" content="width=device-width, initial-scale=1">
@@ -152,21 +178,25 @@ fn moving_webview(time: Res<Time>, mut query: Query<&mut Style, With<WebViewMark
     });
 }
 
-fn log_msgs(mut query: Query<&mut IpcQueue<Msg>>) {
-    let mut ipc = query.single_mut();
-    for i in &mut ipc {
-        println!("{:?}", i);
+fn log_msgs(mut messages: EventReader<WebViewMessage<Msg>>) {
+    for WebViewMessage {
+        entity, message, ..
+    } in messages.read()
+    {
+        println!("{:?}: {:?}", entity, message);
     }
 }
 
 fn text_listener(
     mut events: EventReader<TextInputSubmitEvent>,
-    mut writer: EventWriter<FetchEvent>,
-    query: Query<(&WebViewHandle, &mut IpcSender<String>)>,
+    mut ipc: WebViewIpc<String>,
+    query: Query<Entity, With<WebViewMarker>>,
 ) {
-    if let Ok((wvhandle, ipc_handler)) = query.get_single() {
+    if let Ok(webview) = query.get_single() {
         for event in events.read() {
-            writer.send(ipc_handler.send(*wvhandle, event.value.clone()));
+            if let Err(e) = ipc.send(webview, event.value.clone()) {
+                println!("{}", e);
+            }
         }
     }
 }
@@ -0,0 +1,394 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    ext::IdentExt, meta::ParseNestedMeta, parenthesized, parse_macro_input, spanned::Spanned,
+    Attribute, Data, DeriveInput, Field, Fields, LitStr, Token,
+};
+
+/// Derives `bevy_wry_webview::ipc::typescript::TypeScript`, following the type's `serde`
+/// attributes
+#[proc_macro_derive(TypeScript, attributes(serde))]
+pub fn derive_typescript(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "TypeScript can't be derived for generic types",
+        ));
+    }
+
+    let rt = quote!(::bevy_wry_webview::ipc::typescript);
+    let ident = &input.ident;
+    let name = ident.unraw().to_string();
+    let attrs = ContainerAttrs::parse(&input.attrs)?;
+
+    let body = match &input.data {
+        Data::Struct(data) if attrs.transparent => {
+            let field = data
+                .fields
+                .iter()
+                .find(|x| !FieldAttrs::parse(&x.attrs).is_ok_and(|x| x.is_skipped()))
+                .ok_or_else(|| syn::Error::new(ident.span(), "transparent struct without field"))?;
+            let ty = &field.ty;
+            quote!(<#ty as #rt::TypeScript>::typescript(decls))
+        }
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(_) => {
+                let tag = attrs.tag.as_ref().map(|tag| {
+                    let name = attrs.rename.clone().unwrap_or_else(|| name.clone());
+                    quote!((#tag, false, #rt::literal(#name)))
+                });
+                object(&rt, &data.fields, attrs.rename_all.as_deref(), tag)?
+            }
+            fields => unnamed(&rt, fields)?,
+        },
+        Data::Enum(data) => {
+            let mut pushes = vec![];
+            for variant in &data.variants {
+                let variant_attrs = FieldAttrs::parse(&variant.attrs)?;
+                if variant_attrs.is_skipped() {
+                    continue;
+                }
+                let direction = (
+                    variant_attrs.skip_serializing,
+                    variant_attrs.skip_deserializing,
+                );
+                let variant_name = variant_attrs.rename.unwrap_or_else(|| {
+                    rename_variant(
+                        &variant.ident.unraw().to_string(),
+                        attrs.rename_all.as_deref(),
+                    )
+                });
+                let fields_rename = variant_attrs.rename_all.as_deref();
+
+                let variant = match (&attrs.tag, &attrs.content, attrs.untagged) {
+                    (_, _, true) => match &variant.fields {
+                        Fields::Named(_) => object(&rt, &variant.fields, fields_rename, None)?,
+                        Fields::Unit => quote!(::std::string::String::from("null")),
+                        fields => unnamed(&rt, fields)?,
+                    },
+                    (Some(tag), None, _) => {
+                        let tag = quote!((#tag, false, #rt::literal(#variant_name)));
+                        match &variant.fields {
+                            Fields::Named(_) => {
+                                object(&rt, &variant.fields, fields_rename, Some(tag))?
+                            }
+                            Fields::Unit => quote!(#rt::object(&[#tag], vec![])),
+                            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                                let inner = unnamed(&rt, &variant.fields)?;
+                                quote!(#rt::object(&[#tag], vec![#inner]))
+                            }
+                            fields => {
+                                return Err(syn::Error::new(
+                                    fields.span(),
+                                    "internally tagged enums can't have tuple variants",
+                                ))
+                            }
+                        }
+                    }
+                    (Some(tag), Some(content), _) => {
+                        let tag = quote!((#tag, false, #rt::literal(#variant_name)));
+                        match &variant.fields {
+                            Fields::Unit => quote!(#rt::object(&[#tag], vec![])),
+                            fields => {
+                                let inner = match fields {
+                                    Fields::Named(_) => {
+                                        object(&rt, &variant.fields, fields_rename, None)?
+                                    }
+                                    fields => unnamed(&rt, fields)?,
+                                };
+                                quote!(#rt::object(&[#tag, (#content, false, #inner)], vec![]))
+                            }
+                        }
+                    }
+                    (None, _, _) => match &variant.fields {
+                        Fields::Unit => quote!(#rt::literal(#variant_name)),
+                        fields => {
+                            let inner = match fields {
+                                Fields::Named(_) => {
+                                    object(&rt, &variant.fields, fields_rename, None)?
+                                }
+                                fields => unnamed(&rt, fields)?,
+                            };
+                            quote!(#rt::object(&[(#variant_name, false, #inner)], vec![]))
+                        }
+                    },
+                };
+                pushes.push(push(quote!(variants), direction, variant));
+            }
+            quote!({
+                let mut variants = vec![];
+                #(#pushes)*
+                #rt::union(variants)
+            })
+        }
+        Data::Union(data) => {
+            return Err(syn::Error::new(
+                data.union_token.span,
+                "TypeScript can't be derived for unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl #rt::TypeScript for #ident {
+            fn typescript(decls: &mut #rt::TypeScriptDeclarations) -> ::std::string::String {
+                decls.declare(#name, |decls| #body)
+            }
+        }
+    })
+}
+
+/// An object type of named `fields`, with `tag` as its first property
+fn object(
+    rt: &TokenStream2,
+    fields: &Fields,
+    rename_all: Option<&str>,
+    tag: Option<TokenStream2>,
+) -> syn::Result<TokenStream2> {
+    let mut pushes = tag
+        .into_iter()
+        .map(|tag| quote!(properties.push(#tag);))
+        .collect::<Vec<_>>();
+    for field in fields {
+        let attrs = FieldAttrs::parse(&field.attrs)?;
+        let direction = (attrs.skip_serializing, attrs.skip_deserializing);
+        let ty = &field.ty;
+        let ty = quote!(<#ty as #rt::TypeScript>::typescript(decls));
+        if attrs.flatten {
+            pushes.push(push(quote!(flattened), direction, ty));
+            continue;
+        }
+        let ident = field.ident.as_ref().map(|x| x.unraw().to_string());
+        let key = attrs
+            .rename
+            .unwrap_or_else(|| rename_field(&ident.unwrap_or_default(), rename_all));
+        let optional = attrs.optional;
+        pushes.push(push(
+            quote!(properties),
+            direction,
+            quote!((#key, #optional, #ty)),
+        ));
+    }
+    Ok(quote!({
+        let mut properties: ::std::vec::Vec<(&str, bool, ::std::string::String)> = vec![];
+        let mut flattened = vec![];
+        #(#pushes)*
+        #rt::object(&properties, flattened)
+    }))
+}
+
+/// The type of tuple or unit `fields`; a single field stands for itself
+fn unnamed(rt: &TokenStream2, fields: &Fields) -> syn::Result<TokenStream2> {
+    let typescript = |field: &Field| {
+        let ty = &field.ty;
+        quote!(<#ty as #rt::TypeScript>::typescript(decls))
+    };
+    // Tuples that are left with a single field once some are skipped stay tuples, as in `serde`
+    match fields {
+        Fields::Unit => return Ok(quote!(::std::string::String::from("null"))),
+        fields if fields.len() == 1 => return Ok(typescript(fields.iter().next().unwrap())),
+        _ => {}
+    }
+    let mut pushes = vec![];
+    for field in fields {
+        let attrs = FieldAttrs::parse(&field.attrs)?;
+        let direction = (attrs.skip_serializing, attrs.skip_deserializing);
+        pushes.push(push(quote!(items), direction, typescript(field)));
+    }
+    Ok(quote!({
+        let mut items = vec![];
+        #(#pushes)*
+        #rt::tuple(items)
+    }))
+}
+
+/// Pushes `item` to `list` for the messages that have it, given whether it's
+/// `(skip_serializing, skip_deserializing)`
+///
+/// Bevy serializes the messages it sends to pages, and deserializes those it receives.
+fn push(list: TokenStream2, direction: (bool, bool), item: TokenStream2) -> TokenStream2 {
+    let push = quote!(#list.push(#item););
+    match direction {
+        (false, false) => push,
+        (true, false) => quote!(if !decls.serializing() { #push }),
+        (false, true) => quote!(if decls.serializing() { #push }),
+        (true, true) => quote!(),
+    }
+}
+
+#[derive(Default)]
+struct ContainerAttrs {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    tag: Option<String>,
+    content: Option<String>,
+    untagged: bool,
+    transparent: bool,
+}
+
+impl ContainerAttrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut this = Self::default();
+        for attr in attrs.iter().filter(|x| x.path().is_ident("serde")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    this.rename = Some(name_value(&meta)?);
+                } else if meta.path.is_ident("rename_all") {
+                    this.rename_all = Some(name_value(&meta)?);
+                } else if meta.path.is_ident("tag") {
+                    this.tag = Some(name_value(&meta)?);
+                } else if meta.path.is_ident("content") {
+                    this.content = Some(name_value(&meta)?);
+                } else if meta.path.is_ident("untagged") {
+                    this.untagged = true;
+                } else if meta.path.is_ident("transparent") {
+                    this.transparent = true;
+                } else {
+                    skip(&meta)?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(this)
+    }
+}
+
+/// `serde` attributes of fields and variants
+#[derive(Default)]
+struct FieldAttrs {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    flatten: bool,
+    /// Whether the field is left out of the messages Bevy sends
+    skip_serializing: bool,
+    /// Whether the field is left out of the messages Bevy receives
+    skip_deserializing: bool,
+    /// Whether the field may be missing from messages
+    optional: bool,
+}
+
+impl FieldAttrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut this = Self::default();
+        for attr in attrs.iter().filter(|x| x.path().is_ident("serde")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    this.rename = Some(name_value(&meta)?);
+                } else if meta.path.is_ident("rename_all") {
+                    this.rename_all = Some(name_value(&meta)?);
+                } else if meta.path.is_ident("flatten") {
+                    this.flatten = true;
+                } else if meta.path.is_ident("skip") {
+                    this.skip_serializing = true;
+                    this.skip_deserializing = true;
+                } else if meta.path.is_ident("skip_serializing") {
+                    this.skip_serializing = true;
+                } else if meta.path.is_ident("skip_deserializing") {
+                    this.skip_deserializing = true;
+                } else if meta.path.is_ident("default") || meta.path.is_ident("skip_serializing_if")
+                {
+                    this.optional = true;
+                    skip(&meta)?;
+                } else {
+                    skip(&meta)?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(this)
+    }
+
+    fn is_skipped(&self) -> bool {
+        self.skip_serializing && self.skip_deserializing
+    }
+}
+
+/// The value of `name = "value"`, or of `name(serialize = "value", ...)`
+fn name_value(meta: &ParseNestedMeta) -> syn::Result<String> {
+    if meta.input.peek(Token![=]) {
+        return Ok(meta.value()?.parse::<LitStr>()?.value());
+    }
+    let (mut serialize, mut deserialize) = (None, None);
+    meta.parse_nested_meta(|meta| {
+        let value = meta.value()?.parse::<LitStr>()?.value();
+        if meta.path.is_ident("serialize") {
+            serialize = Some(value);
+        } else {
+            deserialize = Some(value);
+        }
+        Ok(())
+    })?;
+    serialize
+        .or(deserialize)
+        .ok_or_else(|| meta.error("expected a name"))
+}
+
+/// Consumes the value of an attribute that doesn't affect the TypeScript type
+fn skip(meta: &ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        let content;
+        parenthesized!(content in meta.input);
+        content.parse::<TokenStream2>()?;
+    }
+    Ok(())
+}
+
+/// Applies `serde`'s `rename_all` to a `PascalCase` variant name
+fn rename_variant(name: &str, rule: Option<&str>) -> String {
+    let snake = || {
+        let mut snake = String::new();
+        for (i, c) in name.char_indices() {
+            if c.is_uppercase() && i > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    };
+    match rule {
+        Some("lowercase") => name.to_ascii_lowercase(),
+        Some("UPPERCASE") => name.to_ascii_uppercase(),
+        Some("camelCase") => {
+            name.get(..1).unwrap_or_default().to_ascii_lowercase()
+                + name.get(1..).unwrap_or_default()
+        }
+        Some("snake_case") => snake(),
+        Some("SCREAMING_SNAKE_CASE") => snake().to_ascii_uppercase(),
+        Some("kebab-case") => snake().replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => snake().to_ascii_uppercase().replace('_', "-"),
+        _ => name.to_owned(),
+    }
+}
+
+/// Applies `serde`'s `rename_all` to a `snake_case` field name
+fn rename_field(name: &str, rule: Option<&str>) -> String {
+    let pascal = || {
+        name.split('_')
+            .map(|x| {
+                x.get(..1).unwrap_or_default().to_ascii_uppercase() + x.get(1..).unwrap_or_default()
+            })
+            .collect::<String>()
+    };
+    match rule {
+        Some("UPPERCASE") | Some("SCREAMING_SNAKE_CASE") => name.to_ascii_uppercase(),
+        Some("PascalCase") => pascal(),
+        Some("camelCase") => {
+            let pascal = pascal();
+            pascal.get(..1).unwrap_or_default().to_ascii_lowercase()
+                + pascal.get(1..).unwrap_or_default()
+        }
+        Some("kebab-case") => name.replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => name.to_ascii_uppercase().replace('_', "-"),
+        _ => name.to_owned(),
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    path::{Component, Path},
+    sync::{Arc, RwLock},
+};
+
+use bevy::{
+    asset::{io::AssetSourceId, AssetPath, AssetServer, AsyncReadExt},
+    prelude::{Component as EcsComponent, Deref},
+    tasks::IoTaskPool,
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use wry::{
+    http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE},
+        Request, Response,
+    },
+    RequestAsyncResponder,
+};
+
+use crate::lifecycle::WebViewError;
+
+/// Scheme under which Bevy's asset sources are served to webviews
+pub(crate) const ASSET_PROTOCOL: &str = "bevy-asset";
+
+/// Host used for the default asset source
+const DEFAULT_SOURCE_HOST: &str = "localhost";
+
+/// Host used for named asset sources, which take the first segment of the path
+///
+/// Browsers lowercase hosts, so they can't hold names like `MyMod`.
+const NAMED_SOURCE_HOST: &str = "source";
+
+/// Path in the default asset source at which a webview serves its `WebViewLocation::Html`, so
+/// the page's relative links load from the assets
+const INLINE_HTML_PATH: &str = "__webview__.html";
+
+/// Characters escaped in the path of an asset URL; `/` stays as is so relative links resolve
+const PATH_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Characters escaped in the path segment a source name becomes
+const SEGMENT_SET: &AsciiSet = &PATH_SET.add(b'/');
+
+/// Characters escaped in the fragment an asset label becomes
+const FRAGMENT_SET: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'<').add(b'>').add(b'`');
+
+/// The URL serving the asset at `path`, e.g. `ui/index.html` or `embedded://my_crate/ui/index.html`
+///
+/// A label, as in `ui/index.html#settings`, becomes the fragment of the URL.
+pub(crate) fn asset_url(path: &str) -> Result<String, WebViewError> {
+    let asset_path = AssetPath::try_parse(path)
+        .map_err(|e| WebViewError::InvalidLocation(format!("{path}: {e}")))?;
+    let (host, source) = match asset_path.source().as_str() {
+        Some(name) => (
+            NAMED_SOURCE_HOST,
+            format!("{}/", utf8_percent_encode(name, SEGMENT_SET)),
+        ),
+        None => (DEFAULT_SOURCE_HOST, String::new()),
+    };
+    let path = asset_path.path().to_string_lossy().replace('\\', "/");
+    let path = utf8_percent_encode(path.trim_start_matches('/'), PATH_SET);
+    let fragment = asset_path
+        .label()
+        .map(|x| format!("#{}", utf8_percent_encode(x, FRAGMENT_SET)))
+        .unwrap_or_default();
+    Ok(source_url(host, &format!("{source}{path}{fragment}")))
+}
+
+/// The URL serving the `WebViewLocation::Html` of a webview
+pub(crate) fn inline_html_url() -> String {
+    source_url(DEFAULT_SOURCE_HOST, INLINE_HTML_PATH)
+}
+
+fn source_url(host: &str, path: &str) -> String {
+    // Windows only supports custom protocols as `http://<scheme>.<host>`
+    if cfg!(target_os = "windows") {
+        format!("http://{ASSET_PROTOCOL}.{host}/{path}")
+    } else {
+        format!("{ASSET_PROTOCOL}://{host}/{path}")
+    }
+}
+
+/// The HTML of a webview's `WebViewLocation::Html`, as served by its asset protocol
+#[derive(EcsComponent, Clone, Default, Deref)]
+pub(crate) struct InlineHtml(Arc<RwLock<String>>);
+
+impl InlineHtml {
+    pub(crate) fn set(&self, html: &str) {
+        if let Ok(mut x) = self.write() {
+            html.clone_into(&mut x);
+        }
+    }
+}
+
+/// The asset source and path a request of `asset_url` asks for, from the URL's `host` and `path`
+fn requested_asset(host: &str, path: &str) -> Option<(AssetSourceId<'static>, String)> {
+    let host = host
+        .strip_prefix(ASSET_PROTOCOL)
+        .and_then(|x| x.strip_prefix('.'))
+        .unwrap_or(host);
+    let decode = |x: &str| percent_decode_str(x).decode_utf8_lossy().into_owned();
+    let path = path.trim_start_matches('/');
+    match host {
+        DEFAULT_SOURCE_HOST | "" => Some((AssetSourceId::Default, decode(path))),
+        NAMED_SOURCE_HOST => {
+            let (name, path) = path.split_once('/')?;
+            Some((AssetSourceId::from(decode(name)), decode(path)))
+        }
+        _ => None,
+    }
+}
+
+/// Serves `bevy-asset://localhost/<path>` requests from the `AssetServer`'s default source,
+/// `bevy-asset://source/<name>/<path>` from its other sources, and the webview's
+/// `WebViewLocation::Html` from `inline_html_url`
+pub(crate) fn make_asset_protocol(
+    asset_server: AssetServer,
+    html: InlineHtml,
+) -> impl Fn(Request<Vec<u8>>, RequestAsyncResponder) + 'static {
+    move |req: Request<Vec<u8>>, res: RequestAsyncResponder| {
+        let asset_server = asset_server.clone();
+        let host = req.uri().host().unwrap_or_default();
+        let Some((source, path)) = requested_asset(host, req.uri().path()) else {
+            res.respond(Response::builder().status(404).body(vec![]).unwrap());
+            return;
+        };
+
+        if source == AssetSourceId::Default && path == INLINE_HTML_PATH {
+            let html = html.read().map(|x| x.clone()).unwrap_or_default();
+            let response = Response::builder()
+                .status(200)
+                .header(CONTENT_TYPE, "text/html")
+                // Swapped HTML is served under the same URL
+                .header(CACHE_CONTROL, "no-store")
+                .body(html.into_bytes());
+            res.respond(response.unwrap());
+            return;
+        }
+
+        IoTaskPool::get()
+            .spawn(async move {
+                let response = match read_asset(&asset_server, source, &path).await {
+                    Some(bytes) => Response::builder()
+                        .status(200)
+                        .header(
+                            CONTENT_TYPE,
+                            mime_guess::from_path(&path)
+                                .first_or_octet_stream()
+                                .essence_str(),
+                        )
+                        .body(bytes),
+                    None => Response::builder().status(404).body(vec![]),
+                };
+                res.respond(response.unwrap());
+            })
+            .detach();
+    }
+}
+
+async fn read_asset(
+    asset_server: &AssetServer,
+    source: AssetSourceId<'static>,
+    path: &str,
+) -> Option<Vec<u8>> {
+    let path = Path::new(path);
+    // Keep pages from reading files outside of the asset source
+    if path
+        .components()
+        .any(|x| !matches!(x, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let mut reader = asset_server
+        .get_source(source)
+        .ok()?
+        .reader()
+        .read(path)
+        .await
+        .ok()?;
+
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes).await.ok()?;
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asset_urls() {
+        let url = asset_url("ui/my page.html").unwrap();
+        assert!(url.ends_with("localhost/ui/my%20page.html"));
+        let url = asset_url("embedded://my_crate/ui/index.html").unwrap();
+        assert!(url.ends_with("my_crate/ui/index.html"));
+        let url = asset_url("ui/index.html#settings").unwrap();
+        assert!(url.ends_with("localhost/ui/index.html#settings"));
+    }
+
+    #[test]
+    fn asset_urls_keep_source_names() {
+        let request = |path: &str| {
+            let url = asset_url(path).unwrap();
+            let (_, rest) = url.split_once("://").unwrap();
+            let (host, path) = rest.split_once('/').unwrap();
+            // Browsers lowercase the host, but not the path
+            requested_asset(&host.to_ascii_lowercase(), &format!("/{path}")).unwrap()
+        };
+
+        let (source, path) = request("MyMod://ui/My Page.html");
+        assert_eq!(source, AssetSourceId::from("MyMod"));
+        assert_eq!(path, "ui/My Page.html");
+        let (source, _) = request("localhost://ui/index.html");
+        assert_eq!(source, AssetSourceId::from("localhost"));
+        let (source, path) = request("a/b c://index.html");
+        assert_eq!(source, AssetSourceId::from("a/b c"));
+        assert_eq!(path, "index.html");
+        let (source, path) = request("ui/index.html");
+        assert_eq!(source, AssetSourceId::Default);
+        assert_eq!(path, "ui/index.html");
+    }
+
+    #[test]
+    fn invalid_asset_paths_are_errors() {
+        for path in ["ui/a:b.html", "ui/index.html#", "://ui/index.html"] {
+            assert!(matches!(
+                asset_url(path),
+                Err(WebViewError::InvalidLocation(_))
+            ));
+        }
+    }
+}
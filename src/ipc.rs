@@ -1,6 +1,20 @@
-use std::marker::PhantomData;
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError, RwLock,
+    },
+};
 
-use bevy::prelude::{Component, Event, Plugin};
+use bevy::{
+    ecs::system::SystemParam,
+    log::warn,
+    prelude::{
+        App, Component, Deref, Entity, Event, EventWriter, Plugin, PreUpdate, Query, Update,
+    },
+    utils::HashSet,
+};
 use serde::{Deserialize, Serialize};
 use wry::{
     http::{Method, Request, Response},
@@ -9,38 +23,440 @@ use wry::{
 
 use crate::WebViewHandle;
 
+pub mod codec;
+mod frame;
+pub mod limits;
+pub mod origin;
+pub mod reset;
+pub mod rpc;
+pub mod topic;
+pub mod typescript;
+
+use codec::WebViewCodec;
+use frame::Header;
+use limits::{CoalesceKey, OverflowPolicy, Queue, WebViewIpcLimits, WebViewIpcMetrics};
+use origin::{OriginRules, WebViewIpcRejected, WebViewTrustedOrigins};
+use reset::{message_type, WebViewIpcReset, WebViewIpcSticky};
+use rpc::WebViewHandlers;
+
 pub(crate) struct WebViewIpcPlugin;
 
 impl Plugin for WebViewIpcPlugin {
-    fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_event::<FetchEvent>();
+    fn build(&self, app: &mut App) {
+        app.add_event::<FetchEvent>()
+            .add_event::<WebViewIpcReady>()
+            .add_event::<WebViewIpcReset>()
+            .add_event::<WebViewIpcRejected>()
+            .init_resource::<WebViewHandlers>()
+            .add_systems(
+                Update,
+                (
+                    Self::answer_calls,
+                    Self::on_ready,
+                    Self::on_reset,
+                    Self::on_limits_change,
+                    Self::on_sticky_change,
+                    Self::on_trusted_origins_change,
+                    Self::report_rejections,
+                    Self::update_metrics,
+                ),
+            );
     }
 }
 
-#[cfg(not(any(
-    target_os = "linux",
-    target_os = "dragonfly",
-    target_os = "freebsd",
-    target_os = "netbsd",
-    target_os = "openbsd",
-)))]
+impl WebViewIpcPlugin {
+    /// Delivers what was held back for pages that just became ready
+    fn on_ready(
+        channels: Query<(Entity, &WebViewHandle, &IpcChannel)>,
+        mut ready: EventWriter<WebViewIpcReady>,
+        mut writer: EventWriter<FetchEvent>,
+    ) {
+        for (entity, handle, channel) in &channels {
+            if channel.take_became_ready() {
+                writer.send(FetchEvent(*handle));
+                ready.send(WebViewIpcReady { entity });
+            }
+        }
+    }
+}
+
+/// Messages as encoded by the webview's `WebViewCodec`
 type MessageFormat = Vec<u8>;
 
-#[cfg(any(
-    target_os = "linux",
-    target_os = "dragonfly",
-    target_os = "freebsd",
-    target_os = "netbsd",
-    target_os = "openbsd",
-))]
-type MessageFormat = String;
+/// A `window.bevy.invoke` call waiting for its handler
+pub(crate) struct Call {
+    pub(crate) id: u64,
+    pub(crate) name: String,
+    pub(crate) payload: MessageFormat,
+}
+
+/// State shared by the halves of one webview's IPC channel
+#[derive(Default)]
+pub(crate) struct IpcShared {
+    /// Picked when the webview is spawned; `None` until then
+    codec: RwLock<Option<WebViewCodec>>,
+    /// Messages sent before the codec was picked, encoded with it once it is
+    early: Mutex<Vec<ciborium::Value>>,
+    limits: RwLock<WebViewIpcLimits>,
+    /// Payloads of plain messages from the page
+    incoming: Queue<MessageFormat>,
+    /// Frames waiting to be delivered to the page
+    outgoing: Queue<MessageFormat>,
+    /// Replies to the page's calls, which it waits for, so they're never dropped
+    replies: Queue<MessageFormat>,
+    calls: Queue<Call>,
+    /// Calls dropped by the `OverflowPolicy`, until they're answered with an error
+    overflowed: Queue<Call>,
+    /// Topics the page subscribed to with `window.bevy.subscribe`
+    topics: RwLock<HashSet<String>>,
+    /// Whether the page announced that it's ready for frames; they wait in `outgoing` until then
+    ready: AtomicBool,
+    /// Set along with `ready`, until the plugin noticed
+    became_ready: AtomicBool,
+    sticky: RwLock<WebViewIpcSticky>,
+    /// Payloads replayed to the next document, by message type, the most recently sent last
+    last_values: Mutex<Vec<(Option<String>, MessageFormat)>>,
+    /// The document the page last announced, which frames come from
+    document: Mutex<Option<String>>,
+    /// Set when a new document replaced the page's, until the plugin noticed
+    was_reset: AtomicBool,
+    origins: RwLock<OriginRules>,
+    /// Origins of pages whose frames were dropped by `WebViewTrustedOrigins`
+    rejected: Queue<Option<String>>,
+}
+
+impl IpcShared {
+    pub(crate) fn codec(&self) -> WebViewCodec {
+        self.codec
+            .read()
+            .ok()
+            .and_then(|x| x.clone())
+            .unwrap_or_default()
+    }
+
+    /// Picks the codec both sides of the channel use from now on
+    ///
+    /// Messages sent until now are encoded with it and queued in the order they were sent, so
+    /// pick the `WebViewIpcLimits` and `WebViewIpcSticky` that apply to them first.
+    fn set_codec(&self, codec: WebViewCodec) {
+        // Held until the messages are queued, so none sent meanwhile overtakes them
+        let mut picked = self.codec.write().unwrap_or_else(PoisonError::into_inner);
+        let early = self
+            .early
+            .lock()
+            .map(|mut x| std::mem::take(&mut *x))
+            .unwrap_or_default();
+        for msg in early {
+            match codec.encode(&msg) {
+                Ok(data) => self.queue_message(&msg, &data),
+                Err(e) => warn!("Dropping IPC message sent before the webview was spawned: {e}"),
+            }
+        }
+        *picked = Some(codec);
+    }
+
+    fn limits(&self) -> WebViewIpcLimits {
+        self.limits.read().map(|x| *x).unwrap_or_default()
+    }
+
+    pub(crate) fn set_limits(&self, limits: WebViewIpcLimits) {
+        if let Ok(mut x) = self.limits.write() {
+            *x = limits;
+        }
+    }
+
+    pub(crate) fn set_sticky(&self, sticky: WebViewIpcSticky) {
+        if let Ok(mut last_values) = self.last_values.lock() {
+            last_values.retain(|(ty, _)| sticky.includes(ty.as_deref()));
+        }
+        if let Ok(mut x) = self.sticky.write() {
+            *x = sticky;
+        }
+    }
+
+    pub(crate) fn set_trusted_origins(&self, trusted: Option<WebViewTrustedOrigins>) {
+        if let Ok(mut rules) = self.origins.write() {
+            rules.trusted = trusted;
+        }
+    }
+
+    pub(crate) fn set_home(&self, home: Option<String>) {
+        if let Ok(mut rules) = self.origins.write() {
+            rules.home = home;
+        }
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    /// Remembers the URL of the top-level page, which Linux checks trusted origins against
+    pub(crate) fn set_page(&self, url: &str) {
+        if let Ok(mut rules) = self.origins.write() {
+            rules.page = Some(url.to_owned());
+        }
+    }
+
+    /// Whether frames from a page at `source` may pass, reporting the page if not
+    ///
+    /// `None` stands for a page without an origin of its own, e.g. a sandboxed or `data:` frame.
+    fn admits(&self, source: Option<&str>) -> bool {
+        let allowed = self
+            .origins
+            .read()
+            .map(|x| x.allows(source))
+            .unwrap_or(false);
+        if allowed {
+            return true;
+        }
+        let source = source.map(str::to_owned);
+        warn!(
+            "Dropping IPC frame from untrusted page {}",
+            source.as_deref().unwrap_or("without an origin")
+        );
+        self.rejected.push(source, self.limits());
+        false
+    }
+
+    /// Origins of the pages rejected since the last time this was called
+    pub(crate) fn rejections(&self) -> impl Iterator<Item = Option<String>> + '_ {
+        self.rejected.drain()
+    }
+
+    pub(crate) fn metrics(&self) -> WebViewIpcMetrics {
+        WebViewIpcMetrics {
+            incoming_depth: self.incoming.len() + self.calls.len(),
+            outgoing_depth: self.outgoing.len()
+                + self.replies.len()
+                + self.early.lock().map_or(0, |x| x.len()),
+            incoming_dropped: self.incoming.dropped() + self.calls.dropped(),
+            outgoing_dropped: self.outgoing.dropped(),
+        }
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Whether the page became ready since the last time this was called
+    fn take_became_ready(&self) -> bool {
+        self.became_ready.swap(false, Ordering::AcqRel)
+    }
+
+    /// Whether a new document replaced the page's since the last time this was called
+    fn take_was_reset(&self) -> bool {
+        self.was_reset.swap(false, Ordering::AcqRel)
+    }
+
+    /// Takes the frames that follow as coming from `document`, resetting if it replaced another
+    ///
+    /// Only the new document can say it replaced the old one: a load that's cancelled, fails or
+    /// ends in a download leaves the old document in place.
+    fn enter(&self, document: String) {
+        let mut current = self.document.lock().unwrap_or_else(PoisonError::into_inner);
+        if current.as_ref().is_some_and(|x| *x != document) {
+            self.unload();
+        }
+        *current = Some(document);
+    }
+
+    /// Forgets the state of the page's old document, as a new one replaced it
+    ///
+    /// A document that never became ready got no frames, so those stay for the next one.
+    fn unload(&self) {
+        if let Ok(mut topics) = self.topics.write() {
+            topics.clear();
+        }
+        self.calls.drain().for_each(drop);
+        self.overflowed.drain().for_each(drop);
+        if !self.ready.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        self.became_ready.store(false, Ordering::Release);
+
+        self.outgoing.drain().for_each(drop);
+        self.replies.drain().for_each(drop);
+        if let Ok(last_values) = self.last_values.lock() {
+            for (ty, payload) in last_values.iter() {
+                self.outgoing.push_keyed(
+                    frame::encode(&Header::Message, payload),
+                    Some(CoalesceKey::Message(ty.clone())),
+                    self.limits(),
+                );
+            }
+        }
+        self.was_reset.store(true, Ordering::Release);
+    }
+
+    /// Encodes `msg` for the page, or keeps it until the codec is picked
+    fn send(&self, msg: &impl Serialize) -> Result<(), IpcError> {
+        let codec = self.codec.read().unwrap_or_else(PoisonError::into_inner);
+        match &*codec {
+            Some(codec) => {
+                let data = codec.encode(msg).map_err(IpcError::Encode)?;
+                self.queue_message(msg, &data);
+            }
+            None => {
+                // The codec may be any of them, so keep what every codec can encode
+                let msg = ciborium::Value::serialized(msg)
+                    .map_err(|e| IpcError::Encode(e.to_string()))?;
+                if let Ok(mut early) = self.early.lock() {
+                    early.push(msg);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn queue_message(&self, msg: &impl Serialize, payload: &[u8]) {
+        self.remember(msg, payload);
+        let key = self.coalesce_key(|| CoalesceKey::Message(message_type(msg)));
+        self.outgoing
+            .push_keyed(frame::encode(&Header::Message, payload), key, self.limits());
+    }
+
+    /// Tells `OverflowPolicy::Coalesce` what a message replaces, if that's the policy
+    fn coalesce_key(&self, key: impl FnOnce() -> CoalesceKey) -> Option<CoalesceKey> {
+        (self.limits().overflow == OverflowPolicy::Coalesce).then(key)
+    }
+
+    /// Keeps `payload` as the last value of its type, if `WebViewIpcSticky` asks for it
+    fn remember(&self, msg: &impl Serialize, payload: &[u8]) {
+        let Ok(sticky) = self.sticky.read() else {
+            return;
+        };
+        if sticky.is_none() {
+            return;
+        }
+        let ty = message_type(msg);
+        if !sticky.includes(ty.as_deref()) {
+            return;
+        }
+        if let Ok(mut last_values) = self.last_values.lock() {
+            last_values.retain(|(x, _)| *x != ty);
+            last_values.push((ty, payload.to_owned()));
+        }
+    }
+
+    /// Sorts a frame from the page into plain messages and calls
+    fn receive(&self, frame: &[u8]) {
+        match frame::decode(frame) {
+            Some((Header::Message, payload)) => {
+                let key = self.coalesce_key(|| {
+                    let msg = self.codec().decode::<serde_json::Value>(payload);
+                    CoalesceKey::Message(msg.ok().as_ref().and_then(message_type))
+                });
+                self.incoming
+                    .push_keyed(payload.to_owned(), key, self.limits());
+            }
+            Some((Header::Invoke { id, name }, payload)) => {
+                let call = Call {
+                    id,
+                    name,
+                    payload: payload.to_owned(),
+                };
+                for call in self.calls.push(call, self.limits()) {
+                    self.overflowed.push_unbounded(call);
+                }
+            }
+            Some((Header::Subscribe { topic }, _)) => {
+                if let Ok(mut topics) = self.topics.write() {
+                    topics.insert(topic);
+                }
+            }
+            Some((Header::Unsubscribe { topic }, _)) => {
+                if let Ok(mut topics) = self.topics.write() {
+                    topics.remove(&topic);
+                }
+            }
+            Some((Header::Load { document }, _)) => self.enter(document),
+            Some((Header::Ready { document }, _)) => {
+                self.enter(document);
+                if !self.ready.swap(true, Ordering::AcqRel) {
+                    self.became_ready.store(true, Ordering::Release);
+                }
+            }
+            Some((Header::Reply { .. } | Header::Publish { .. }, _)) | None => {
+                warn!("Dropping malformed IPC frame")
+            }
+        }
+    }
+
+    pub(crate) fn is_subscribed(&self, topic: &str) -> bool {
+        self.topics
+            .read()
+            .map(|x| x.contains(topic))
+            .unwrap_or(false)
+    }
+
+    /// Calls the page made since the last time this was called
+    pub(crate) fn calls(&self) -> impl Iterator<Item = Call> {
+        self.calls.drain()
+    }
+
+    /// Calls dropped by the `OverflowPolicy` since the last time this was called
+    pub(crate) fn overflowed_calls(&self) -> impl Iterator<Item = Call> {
+        self.overflowed.drain()
+    }
+
+    /// Queues a frame for the page, returning the event that delivers it once the page is ready
+    pub(crate) fn push(
+        &self,
+        handle: WebViewHandle,
+        header: &Header,
+        payload: &[u8],
+    ) -> FetchEvent {
+        let frame = frame::encode(header, payload);
+        match header {
+            Header::Reply { .. } => self.replies.push_unbounded(frame),
+            Header::Publish { topic } => {
+                let key = self.coalesce_key(|| CoalesceKey::Topic(topic.clone()));
+                self.outgoing.push_keyed(frame, key, self.limits());
+            }
+            _ => {
+                self.outgoing.push(frame, self.limits());
+            }
+        }
+        FetchEvent(handle)
+    }
+
+    /// Takes the frames waiting for the page, replies first
+    fn take_outgoing(&self) -> impl Iterator<Item = MessageFormat> {
+        self.replies.drain().chain(self.outgoing.drain())
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    /// Takes the frames waiting for the page, as the script that hands them to it on Linux
+    pub(crate) fn take_fetch_script(&self) -> String {
+        let codec = self.codec();
+        self.take_outgoing()
+            .map(|frame| {
+                let text = to_wire_text(&codec, frame);
+                format!("window.__bevy.fetch({});", js_string(&text))
+            })
+            .collect()
+    }
+}
+
+/// The IPC channel of a spawned webview, as seen by the plugin
+#[derive(Component, Clone, Deref)]
+pub(crate) struct IpcChannel(Arc<IpcShared>);
 
 #[derive(Component)]
 pub struct IpcSender<T>
 where
     T: Serialize + Send + Sync,
 {
-    sender: crossbeam::Sender<MessageFormat>,
+    shared: Arc<IpcShared>,
     _phantom_data: PhantomData<T>,
 }
 
@@ -49,37 +465,127 @@ pub struct IpcQueue<U>
 where
     U: for<'a> Deserialize<'a> + Send + Sync,
 {
-    receiver: crossbeam::Receiver<MessageFormat>,
+    shared: Arc<IpcShared>,
     _phantom_data: PhantomData<U>,
 }
 
+/// Sent once the page of `entity` is loaded and its `window.bevy` client is listening
+///
+/// Messages sent before then are held back, and delivered in order right after this. Pages that
+/// set `bevy.autoReady = false` are ready once they call `bevy.markReady()` instead of once loaded.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct WebViewIpcReady {
+    pub entity: Entity,
+}
+
+/// A message from the page of `entity`, sent by `WebViewMessagePlugin<U>`
+#[derive(Event, Clone, Debug)]
+pub struct WebViewMessage<U>
+where
+    U: Send + Sync + 'static,
+{
+    pub entity: Entity,
+    pub handle: WebViewHandle,
+    pub message: U,
+}
+
+/// Drains every `IpcQueue<U>` into `WebViewMessage<U>` events once per frame, before `Update`
+///
+/// With it added, read messages through `EventReader<WebViewMessage<U>>` rather than the queues;
+/// malformed messages are logged and skipped.
+pub struct WebViewMessagePlugin<U>(PhantomData<fn() -> U>);
+
+impl<U> Default for WebViewMessagePlugin<U> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<U> Plugin for WebViewMessagePlugin<U>
+where
+    U: for<'a> Deserialize<'a> + Send + Sync + 'static,
+{
+    fn build(&self, app: &mut App) {
+        app.add_event::<WebViewMessage<U>>()
+            .add_systems(PreUpdate, Self::drain_queues);
+    }
+}
+
+impl<U> WebViewMessagePlugin<U>
+where
+    U: for<'a> Deserialize<'a> + Send + Sync + 'static,
+{
+    fn drain_queues(
+        mut queues: Query<(Entity, &WebViewHandle, &mut IpcQueue<U>)>,
+        mut writer: EventWriter<WebViewMessage<U>>,
+    ) {
+        for (entity, handle, mut queue) in &mut queues {
+            writer.send_batch(queue.by_ref().map(|message| WebViewMessage {
+                entity,
+                handle: *handle,
+                message,
+            }));
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum IpcError {
+    /// A message from the page didn't match the expected type
+    Decode { raw: MessageFormat, error: String },
+    /// A message couldn't be serialized for the page
+    Encode(String),
+    /// A message was sent to an entity without a matching `IpcSender`
+    NoWebView(Entity),
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpcError::Decode { error, .. } => write!(f, "failed to decode IPC message: {error}"),
+            IpcError::Encode(error) => write!(f, "failed to encode IPC message: {error}"),
+            IpcError::NoWebView(entity) => write!(f, "{entity:?} has no webview to send to"),
+        }
+    }
+}
+
+impl std::error::Error for IpcError {}
+
 #[derive(Component, Clone)]
 pub struct TemporaryIpcStore {
-    sender: crossbeam::Sender<MessageFormat>,
-    receiver: crossbeam::Receiver<MessageFormat>,
+    shared: Arc<IpcShared>,
 }
 
-#[cfg(not(any(
-    target_os = "linux",
-    target_os = "dragonfly",
-    target_os = "freebsd",
-    target_os = "netbsd",
-    target_os = "openbsd",
-)))]
 #[derive(Event)]
 pub struct FetchEvent(pub(crate) WebViewHandle);
 
-#[cfg(any(
-    target_os = "linux",
-    target_os = "dragonfly",
-    target_os = "freebsd",
-    target_os = "netbsd",
-    target_os = "openbsd",
-))]
-#[derive(Event)]
-pub struct FetchEvent(pub(crate) WebViewHandle, pub(crate) String);
-
 impl TemporaryIpcStore {
+    pub(crate) fn set_codec(&self, codec: WebViewCodec) {
+        self.shared.set_codec(codec.clone());
+    }
+
+    pub(crate) fn set_limits(&self, limits: WebViewIpcLimits) {
+        self.shared.set_limits(limits);
+    }
+
+    pub(crate) fn set_sticky(&self, sticky: WebViewIpcSticky) {
+        self.shared.set_sticky(sticky);
+    }
+
+    pub(crate) fn set_trusted_origins(
+        &self,
+        trusted: Option<WebViewTrustedOrigins>,
+        home: Option<String>,
+    ) {
+        self.shared.set_trusted_origins(trusted);
+        self.shared.set_home(home);
+    }
+
+    /// The channel as kept by the plugin once the webview is spawned
+    pub(crate) fn channel(&self) -> IpcChannel {
+        IpcChannel(self.shared.clone())
+    }
+
     #[cfg(not(any(
         target_os = "linux",
         target_os = "dragonfly",
@@ -89,22 +595,25 @@ impl TemporaryIpcStore {
     )))]
     pub fn make_async_protocol(self) -> impl Fn(Request<Vec<u8>>, RequestAsyncResponder) + 'static {
         let func = move |req: Request<Vec<u8>>, res: RequestAsyncResponder| {
-            if (req.uri() == "bevy://send" || req.uri() == "bevy://send/")
+            // Pages without an origin of their own, like sandboxed frames, send "null"
+            let source = req
+                .headers()
+                .get("Origin")
+                .and_then(|x| x.to_str().ok())
+                .filter(|x| *x != "null");
+            if !self.shared.admits(source) {
+                res.respond(Response::builder().status(403).body(vec![]).unwrap());
+            } else if (req.uri() == "bevy://send" || req.uri() == "bevy://send/")
                 && req.method() == Method::POST
             {
-                let _ = self.sender.send(req.body().to_owned());
+                self.shared.receive(req.body());
                 res.respond(Response::builder().status(200).body(vec![]).unwrap());
             } else if (req.uri() == "bevy://fetch" || req.uri() == "bevy://fetch/")
                 && req.method() == Method::GET
             {
-                //let _ = fsender_cloned.send((WebViewHandle(Some(len)), x, data_tx.clone()));
-
-                match self.receiver.recv() {
-                    Ok(data) if !data.is_empty() => {
-                        res.respond(Response::builder().status(200).body(data).unwrap())
-                    }
-                    _ => res.respond(Response::builder().status(404).body(vec![]).unwrap()),
-                }
+                // Hand out everything waiting at once; fetches never wait for frames to arrive
+                let batch = frame::batch(self.shared.take_outgoing());
+                res.respond(Response::builder().status(200).body(batch).unwrap());
             } else {
                 res.respond(Response::builder().status(404).body(vec![]).unwrap());
             }
@@ -122,7 +631,15 @@ impl TemporaryIpcStore {
     ))]
     pub fn make_ipc_handler(self) -> impl Fn(String) + 'static {
         move |message: String| {
-            let _ = self.sender.send(message);
+            // Messages don't say which frame sent them, so all are taken for the top-level page
+            let page = self.shared.origins.read().ok().and_then(|x| x.page.clone());
+            if !self.shared.admits(page.as_deref()) {
+                return;
+            }
+            match from_wire_text(&self.shared.codec(), message) {
+                Some(frame) => self.shared.receive(&frame),
+                None => warn!("Dropping IPC message that isn't valid base64"),
+            }
         }
     }
 }
@@ -132,87 +649,391 @@ where
     T: Serialize + Send + Sync,
     U: for<'a> Deserialize<'a> + Send + Sync,
 {
-    let (incoming_send, incoming_receive) = crossbeam::unbounded();
-    let (outgoing_send, outgoing_receive) = crossbeam::unbounded();
+    let shared = Arc::new(IpcShared::default());
     (
         IpcSender {
-            sender: outgoing_send,
+            shared: shared.clone(),
             _phantom_data: PhantomData,
         },
         IpcQueue {
-            receiver: incoming_receive,
+            shared: shared.clone(),
             _phantom_data: PhantomData,
         },
-        TemporaryIpcStore {
-            sender: incoming_send,
-            receiver: outgoing_receive,
-        },
+        TemporaryIpcStore { shared },
     )
 }
 
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+/// Quotes `text` as a JS string literal that evaluates back to exactly `text`, whatever it contains
+pub(crate) fn js_string(text: &str) -> String {
+    // JSON string literals are JS string literals, except that JS engines predating ES2019
+    // treat raw line and paragraph separators as line breaks
+    serde_json::to_string(text)
+        .unwrap_or_default()
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029")
+}
+
+/// Linux only carries strings between Rust and the page; frames of binary codecs travel as base64
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+fn to_wire_text(codec: &WebViewCodec, data: MessageFormat) -> String {
+    use base64::Engine;
+
+    if codec.is_binary() {
+        base64::engine::general_purpose::STANDARD.encode(data)
+    } else {
+        String::from_utf8_lossy(&data).into_owned()
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+fn from_wire_text(codec: &WebViewCodec, text: String) -> Option<MessageFormat> {
+    use base64::Engine;
+
+    if codec.is_binary() {
+        base64::engine::general_purpose::STANDARD.decode(text).ok()
+    } else {
+        Some(text.into_bytes())
+    }
+}
+
 impl<T> IpcSender<T>
 where
     T: Serialize + Send + Sync,
 {
-    #[cfg(not(any(
-        target_os = "linux",
-        target_os = "dragonfly",
-        target_os = "freebsd",
-        target_os = "netbsd",
-        target_os = "openbsd",
-    )))]
-    #[must_use]
-    /// Generate message send event
-    pub fn send(&self, handle: WebViewHandle, msg: T) -> FetchEvent {
-        let _ = self.sender.send(rmp_serde::to_vec(&msg).unwrap());
-        FetchEvent(handle)
+    /// Generate message send event, or fail if `msg` can't be encoded
+    pub fn send(&self, handle: WebViewHandle, msg: T) -> Result<FetchEvent, IpcError> {
+        self.send_ref(handle, &msg)
     }
 
-    #[cfg(any(
-        target_os = "linux",
-        target_os = "dragonfly",
-        target_os = "freebsd",
-        target_os = "netbsd",
-        target_os = "openbsd",
-    ))]
-    #[must_use]
-    /// Generate message send event
-    pub fn send(&self, handle: WebViewHandle, msg: T) -> FetchEvent {
-        FetchEvent(handle, serde_json::to_string(&msg).unwrap())
+    fn send_ref(&self, handle: WebViewHandle, msg: &T) -> Result<FetchEvent, IpcError> {
+        self.shared.send(msg)?;
+        Ok(FetchEvent(handle))
     }
 }
 
+/// Sends messages to the pages of webviews by entity, without needing to forward `FetchEvent`s
+#[derive(SystemParam)]
+pub struct WebViewIpc<'w, 's, T>
+where
+    T: Serialize + Send + Sync + 'static,
+{
+    senders: Query<'w, 's, (&'static WebViewHandle, &'static IpcSender<T>)>,
+    writer: EventWriter<'w, FetchEvent>,
+}
+
+impl<T> WebViewIpc<'_, '_, T>
+where
+    T: Serialize + Send + Sync + 'static,
+{
+    /// Sends `msg` to the page of the `UiWebViewBundle<T, _>` on `entity`
+    pub fn send(&mut self, entity: Entity, msg: T) -> Result<(), IpcError> {
+        let (handle, sender) = self
+            .senders
+            .get(entity)
+            .map_err(|_| IpcError::NoWebView(entity))?;
+        self.writer.send(sender.send(*handle, msg)?);
+        Ok(())
+    }
+
+    /// Sends `msg` to the pages of all `UiWebViewBundle<T, _>`s
+    ///
+    /// Every webview is sent to, even if some fail; the first error is returned.
+    pub fn broadcast(&mut self, msg: &T) -> Result<(), IpcError> {
+        let mut result = Ok(());
+        for (handle, sender) in &self.senders {
+            match sender.send_ref(*handle, msg) {
+                Ok(fetch) => self.writer.send(fetch),
+                Err(e) => result = result.and(Err(e)),
+            }
+        }
+        result
+    }
+}
+
+impl<U> IpcQueue<U>
+where
+    U: for<'a> Deserialize<'a> + Send + Sync,
+{
+    /// Drains the queue, yielding malformed messages as errors instead of skipping them
+    pub fn try_iter(&mut self) -> impl Iterator<Item = Result<U, IpcError>> + '_ {
+        let codec = self.shared.codec();
+        self.shared
+            .incoming
+            .drain()
+            .map(move |raw| Self::decode(&codec, raw))
+    }
+
+    fn decode(codec: &WebViewCodec, raw: MessageFormat) -> Result<U, IpcError> {
+        codec
+            .decode::<U>(&raw)
+            .map_err(|error| IpcError::Decode { raw, error })
+    }
+}
+
+/// Yields well-formed messages; malformed ones are logged and skipped, see `IpcQueue::try_iter`
 impl<U> Iterator for IpcQueue<U>
 where
     U: for<'a> Deserialize<'a> + Send + Sync,
 {
     type Item = U;
 
-    #[cfg(not(any(
-        target_os = "linux",
-        target_os = "dragonfly",
-        target_os = "freebsd",
-        target_os = "netbsd",
-        target_os = "openbsd",
-    )))]
     fn next(&mut self) -> Option<Self::Item> {
-        self.receiver
-            .try_recv()
-            .ok()
-            .map(|x| rmp_serde::from_slice::<U>(&x).unwrap())
+        loop {
+            match Self::decode(&self.shared.codec(), self.shared.incoming.pop()?) {
+                Ok(msg) => return Some(msg),
+                Err(e) => warn!("Dropping IPC message: {e}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(tag = "type")]
+    enum Msg {
+        Score { points: f32, bytes: Vec<u8> },
+        Name(Named),
     }
 
-    #[cfg(any(
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Named {
+        name: Option<String>,
+    }
+
+    /// The messages waiting for the page, decoded
+    fn outgoing(shared: &IpcShared) -> Vec<Msg> {
+        let codec = shared.codec();
+        shared
+            .outgoing
+            .drain()
+            .map(|frame| {
+                let (header, payload) = frame::decode(&frame).unwrap();
+                assert_eq!(header, Header::Message);
+                codec.decode(payload).unwrap()
+            })
+            .collect()
+    }
+
+    fn load(document: &str) -> Vec<u8> {
+        let document = document.to_owned();
+        frame::encode(&Header::Load { document }, b"null")
+    }
+
+    fn ready(document: &str) -> Vec<u8> {
+        let document = document.to_owned();
+        frame::encode(&Header::Ready { document }, b"null")
+    }
+
+    #[test]
+    fn messages_sent_before_spawn_use_the_picked_codec() {
+        let messages = || {
+            vec![
+                Msg::Score {
+                    points: 1.5,
+                    bytes: vec![0, 255],
+                },
+                Msg::Name(Named { name: None }),
+                Msg::Score {
+                    points: 2.0,
+                    bytes: vec![],
+                },
+            ]
+        };
+        for codec in [WebViewCodec::Json, WebViewCodec::Cbor] {
+            let (sender, _, store) = new_ipc_channel::<Msg, ()>();
+            let [first, second, third]: [Msg; 3] = messages().try_into().unwrap();
+            sender.send(WebViewHandle(None), first).unwrap();
+            sender.send(WebViewHandle(None), second).unwrap();
+            assert_eq!(store.shared.metrics().outgoing_depth, 2);
+
+            // As `on_webview_spawn` does
+            store.set_sticky(WebViewIpcSticky::All);
+            store.set_codec(codec);
+            sender.send(WebViewHandle(None), third).unwrap();
+            assert_eq!(outgoing(&store.shared), messages());
+
+            // Early messages are remembered for the next document too
+            store.shared.receive(&ready("old"));
+            store.shared.receive(&load("new"));
+            assert_eq!(&outgoing(&store.shared)[..], &messages()[1..]);
+        }
+    }
+
+    #[test]
+    fn only_a_new_document_resets_the_page() {
+        let shared = IpcShared::default();
+        shared.set_codec(WebViewCodec::Json);
+        shared.set_sticky(WebViewIpcSticky::All);
+        shared.receive(&load("first"));
+        shared.receive(&frame::encode(
+            &Header::Subscribe { topic: "t".into() },
+            b"null",
+        ));
+        shared.receive(&ready("first"));
+        assert!(shared.is_ready() && shared.take_became_ready());
+        shared.send(&Msg::Name(Named { name: None })).unwrap();
+
+        // Until another document announces itself, e.g. not after a download, nothing resets
+        shared.receive(&ready("first"));
+        assert!(shared.is_ready() && shared.is_subscribed("t"));
+        assert!(!shared.take_became_ready() && !shared.take_was_reset());
+
+        shared.receive(&load("second"));
+        assert!(!shared.is_ready() && !shared.is_subscribed("t"));
+        assert!(shared.take_was_reset());
+        assert_eq!(outgoing(&shared), [Msg::Name(Named { name: None })]);
+        shared.receive(&ready("second"));
+        assert!(shared.is_ready() && !shared.take_was_reset());
+    }
+
+    #[test]
+    fn overflow_spares_replies_and_answers_dropped_calls() {
+        let shared = IpcShared::default();
+        shared.set_codec(WebViewCodec::Json);
+        shared.set_limits(WebViewIpcLimits {
+            capacity: 1,
+            overflow: OverflowPolicy::Coalesce,
+        });
+        let handle = WebViewHandle(None);
+        let reply = Header::Reply { id: 7, ok: true };
+        shared.push(handle, &reply, b"1");
+        shared.send(&Msg::Name(Named { name: None })).unwrap();
+        shared.push(handle, &reply, b"2");
+        shared.send(&Msg::Name(Named { name: None })).unwrap();
+        let frames = shared
+            .take_outgoing()
+            .map(|x| frame::decode(&x).map(|(header, _)| header).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(frames, [reply.clone(), reply, Header::Message]);
+        assert_eq!(shared.metrics().outgoing_dropped, 1);
+
+        for id in 0..2 {
+            let name = format!("call{id}");
+            let frame = frame::encode(&Header::Invoke { id, name }, b"null");
+            shared.receive(&frame);
+        }
+        let ids = |x: &mut dyn Iterator<Item = Call>| x.map(|call| call.id).collect::<Vec<_>>();
+        assert_eq!(ids(&mut shared.calls()), [1]);
+        assert_eq!(ids(&mut shared.overflowed_calls()), [0]);
+    }
+}
+
+// Only Linux carries messages to pages in scripts
+#[cfg(all(
+    test,
+    any(
         target_os = "linux",
         target_os = "dragonfly",
         target_os = "freebsd",
         target_os = "netbsd",
         target_os = "openbsd",
-    ))]
-    fn next(&mut self) -> Option<Self::Item> {
-        self.receiver
-            .try_recv()
-            .ok()
-            .map(|x| serde_json::from_str::<U>(&x).unwrap())
+    )
+))]
+mod linux_tests {
+    use super::*;
+
+    /// Game data that broke, or could break, the scripts carrying messages to Linux pages
+    const ADVERSARIAL: &[&str] = &[
+        "",
+        "`${alert(1)}`",
+        "`; alert(1); `",
+        "back\\slash\\",
+        "\\u0041\\x41\\",
+        "\"); alert(1); (\"",
+        "'single' \"double\"",
+        "</script><script>alert(1)</script>",
+        "line\u{2028}separator\u{2029}paragraph",
+        "new\nline\r\ttab",
+        "nul\0byte",
+        "\u{1b}[31m\u{7f}",
+        "ünïcödé 中文 🎮 \u{10ffff}",
+    ];
+
+    #[test]
+    fn js_string_round_trips() {
+        for &text in ADVERSARIAL {
+            let literal = js_string(text);
+            assert!(literal.starts_with('"') && literal.ends_with('"'));
+            // Raw line breaks end string literals in JS
+            assert!(!literal.contains(['\n', '\r', '\u{2028}', '\u{2029}']));
+            // A JSON string can't end early, and parsing it fails on anything after its end
+            assert_eq!(serde_json::from_str::<String>(&literal).unwrap(), text);
+        }
+    }
+
+    const CODECS: [WebViewCodec; 3] = [
+        WebViewCodec::Json,
+        WebViewCodec::MessagePack,
+        WebViewCodec::Cbor,
+    ];
+
+    #[test]
+    fn wire_text_round_trips() {
+        for codec in CODECS {
+            for &text in ADVERSARIAL {
+                let frame = frame::encode(&Header::Message, &codec.encode(&text).unwrap());
+                let wire = to_wire_text(&codec, frame.clone());
+                assert_eq!(from_wire_text(&codec, wire), Some(frame));
+            }
+        }
+
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        for codec in [WebViewCodec::MessagePack, WebViewCodec::Cbor] {
+            let wire = to_wire_text(&codec, bytes.clone());
+            assert_eq!(from_wire_text(&codec, wire), Some(bytes.clone()));
+        }
+    }
+
+    #[test]
+    fn fetch_script_delivers_messages_verbatim() {
+        for codec in CODECS {
+            let shared = IpcShared::default();
+            shared.set_codec(codec.clone());
+            for &text in ADVERSARIAL {
+                shared.push(
+                    WebViewHandle(None),
+                    &Header::Message,
+                    &codec.encode(&text).unwrap(),
+                );
+            }
+
+            // The script must be nothing but one `fetch` call per message, with its frame intact
+            let script = shared.take_fetch_script();
+            let mut rest = script.as_str();
+            for &text in ADVERSARIAL {
+                rest = rest.strip_prefix("window.__bevy.fetch(").unwrap();
+                let mut literals = serde_json::Deserializer::from_str(rest).into_iter::<String>();
+                let wire = literals.next().unwrap().unwrap();
+                rest = rest[literals.byte_offset()..].strip_prefix(");").unwrap();
+
+                let frame = from_wire_text(&codec, wire).unwrap();
+                let (header, payload) = frame::decode(&frame).unwrap();
+                assert_eq!(header, Header::Message);
+                assert_eq!(codec.decode::<String>(payload).unwrap(), text);
+            }
+            assert_eq!(rest, "");
+        }
     }
 }
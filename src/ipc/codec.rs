@@ -0,0 +1,471 @@
+use std::{fmt, mem, sync::Arc};
+
+use bevy::prelude::{Component, Resource};
+use ciborium::Value;
+use serde::{
+    de::{
+        self,
+        value::{MapDeserializer, SeqDeserializer},
+        DeserializeOwned, Deserializer, EnumAccess, IntoDeserializer, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any, Serialize,
+};
+
+/// Turns IPC messages into bytes on the wire and back
+///
+/// Plug in a codec of your own with `WebViewCodec::custom`. The page looks up its side of a codec
+/// by `name` in `window.__bevy.codecs`, where the built-in ones come from `assets/codecs.js`.
+pub trait IpcCodec: Send + Sync + 'static {
+    /// Name of the matching JS codec
+    fn name(&self) -> &str;
+
+    /// Whether encoded messages are arbitrary bytes rather than UTF-8 text
+    fn is_binary(&self) -> bool;
+
+    /// Script run in every page before its own, adding the page side of the codec as
+    /// `window.__bevy.codecs[name] = { binary, encode: (msg) => bytes, decode: (bytes) => msg }`
+    fn script(&self) -> Option<&str> {
+        None
+    }
+
+    fn encode(&self, msg: &dyn erased_serde::Serialize) -> Result<Vec<u8>, String>;
+
+    /// Hands a deserializer of `bytes` to `visit`, which deserializes the message with it
+    fn decode<'de>(&self, bytes: &'de [u8], visit: Visit<'_, 'de>) -> Result<(), String>;
+}
+
+/// Deserializes a message from the deserializer an `IpcCodec` hands it
+pub type Visit<'a, 'de> =
+    &'a mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonCodec;
+
+impl IpcCodec for JsonCodec {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn is_binary(&self) -> bool {
+        false
+    }
+
+    fn encode(&self, msg: &dyn erased_serde::Serialize) -> Result<Vec<u8>, String> {
+        serde_json::to_vec(msg).map_err(|e| e.to_string())
+    }
+
+    fn decode<'de>(&self, bytes: &'de [u8], visit: Visit<'_, 'de>) -> Result<(), String> {
+        let mut de = serde_json::Deserializer::from_slice(bytes);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de)).map_err(|e| e.to_string())?;
+        de.end().map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePackCodec;
+
+impl IpcCodec for MessagePackCodec {
+    fn name(&self) -> &str {
+        "msgpack"
+    }
+
+    fn is_binary(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, msg: &dyn erased_serde::Serialize) -> Result<Vec<u8>, String> {
+        // Structs become maps, so pages see the same shapes as with the other codecs
+        rmp_serde::to_vec_named(msg).map_err(|e| e.to_string())
+    }
+
+    fn decode<'de>(&self, bytes: &'de [u8], visit: Visit<'_, 'de>) -> Result<(), String> {
+        let mut de = rmp_serde::Deserializer::from_read_ref(bytes);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de)).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CborCodec;
+
+impl IpcCodec for CborCodec {
+    fn name(&self) -> &str {
+        "cbor"
+    }
+
+    fn is_binary(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, msg: &dyn erased_serde::Serialize) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![];
+        ciborium::into_writer(msg, &mut bytes).map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+
+    fn decode<'de>(&self, bytes: &'de [u8], visit: Visit<'_, 'de>) -> Result<(), String> {
+        let value: Value = ciborium::from_reader(bytes).map_err(|e| e.to_string())?;
+        visit(&mut <dyn erased_serde::Deserializer>::erase(Lenient(value)))
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Deserializes a CBOR value, taking integers for float fields
+///
+/// Pages write every integral number as a CBOR integer, as JS doesn't tell `1` and `1.0` apart,
+/// while `ciborium` only takes CBOR floats for `f32` and `f64`.
+struct Lenient(Value);
+
+impl<'de> Deserializer<'de> for Lenient {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Integer(x) => {
+                let x = i128::from(x);
+                if let Ok(x) = u64::try_from(x) {
+                    visitor.visit_u64(x)
+                } else if let Ok(x) = i64::try_from(x) {
+                    visitor.visit_i64(x)
+                } else {
+                    visitor.visit_i128(x)
+                }
+            }
+            Value::Bytes(x) => visitor.visit_byte_buf(x),
+            Value::Float(x) => visitor.visit_f64(x),
+            Value::Text(x) => visitor.visit_string(x),
+            Value::Bool(x) => visitor.visit_bool(x),
+            Value::Null => visitor.visit_unit(),
+            Value::Tag(_, x) => Lenient(*x).deserialize_any(visitor),
+            Value::Array(x) => {
+                let mut seq = SeqDeserializer::new(x.into_iter().map(Lenient));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Map(x) => {
+                let mut map =
+                    MapDeserializer::new(x.into_iter().map(|(k, v)| (Lenient(k), Lenient(v))));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            _ => Err(de::Error::custom("unsupported CBOR value")),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            value => visitor.visit_some(Lenient(value)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            // Unit variants are written as their name, others as a map of name to content
+            Value::Text(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Value::Map(mut x) if x.len() == 1 => {
+                let (variant, content) = x.remove(0);
+                visitor.visit_enum(Lenient(variant).with(content))
+            }
+            Value::Tag(_, x) => Lenient(*x).deserialize_enum(name, variants, visitor),
+            _ => Err(de::Error::custom("expected an enum")),
+        }
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, de::value::Error> for Lenient {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl Lenient {
+    fn with(self, content: Value) -> LenientVariant {
+        LenientVariant(self, Lenient(content))
+    }
+}
+
+/// The variant and content of a non-unit enum variant
+struct LenientVariant(Lenient, Lenient);
+
+impl<'de> EnumAccess<'de> for LenientVariant {
+    type Error = de::value::Error;
+    type Variant = Lenient;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Lenient), Self::Error> {
+        Ok((seed.deserialize(self.0)?, self.1))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Lenient {
+    type Error = de::value::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+}
+
+/// The codec of a webview's IPC, picked when the webview is spawned
+#[derive(Component, Resource, Clone, Default)]
+pub enum WebViewCodec {
+    Json,
+    #[default]
+    MessagePack,
+    Cbor,
+    /// A codec of your own, see `IpcCodec`
+    Custom(Arc<dyn IpcCodec>),
+}
+
+impl WebViewCodec {
+    pub fn custom(codec: impl IpcCodec) -> Self {
+        WebViewCodec::Custom(Arc::new(codec))
+    }
+
+    fn codec(&self) -> &dyn IpcCodec {
+        match self {
+            WebViewCodec::Json => &JsonCodec,
+            WebViewCodec::MessagePack => &MessagePackCodec,
+            WebViewCodec::Cbor => &CborCodec,
+            WebViewCodec::Custom(codec) => &**codec,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        self.codec().name()
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    pub(crate) fn is_binary(&self) -> bool {
+        self.codec().is_binary()
+    }
+
+    pub(crate) fn script(&self) -> &str {
+        self.codec().script().unwrap_or_default()
+    }
+
+    pub(crate) fn encode<T: Serialize>(&self, msg: &T) -> Result<Vec<u8>, String> {
+        self.codec().encode(msg)
+    }
+
+    pub(crate) fn decode<U: DeserializeOwned>(&self, bytes: &[u8]) -> Result<U, String> {
+        let mut msg = None;
+        self.codec().decode(bytes, &mut |de| {
+            msg = Some(erased_serde::deserialize(de)?);
+            Ok(())
+        })?;
+        msg.ok_or_else(|| format!("the {} codec decoded nothing", self.name()))
+    }
+}
+
+impl PartialEq for WebViewCodec {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (WebViewCodec::Custom(a), WebViewCodec::Custom(b)) => {
+                Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const ()
+            }
+            (a, b) => mem::discriminant(a) == mem::discriminant(b),
+        }
+    }
+}
+
+impl fmt::Debug for WebViewCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebViewCodec::Json => f.write_str("Json"),
+            WebViewCodec::MessagePack => f.write_str("MessagePack"),
+            WebViewCodec::Cbor => f.write_str("Cbor"),
+            WebViewCodec::Custom(codec) => f.debug_tuple("Custom").field(&codec.name()).finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Point,
+        Circle { radius: f32 },
+        Moved(Box<Shape>, [f64; 2]),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Message {
+        x: f64,
+        y: f32,
+        count: u32,
+        offset: i64,
+        name: Option<String>,
+        shapes: Vec<Shape>,
+    }
+
+    #[test]
+    fn cbor_takes_integers_for_floats() {
+        // As the page encodes `{ x: 1, y: -2, ... }`, integral numbers being CBOR integers
+        let value = Value::Map(vec![
+            (Value::from("x"), Value::from(1)),
+            (Value::from("y"), Value::from(-2)),
+            (Value::from("count"), Value::from(3)),
+            (Value::from("offset"), Value::from(-4)),
+            (Value::from("name"), Value::Null),
+            (
+                Value::from("shapes"),
+                Value::Array(vec![
+                    Value::from("Point"),
+                    Value::Map(vec![(
+                        Value::from("Circle"),
+                        Value::Map(vec![(Value::from("radius"), Value::from(5))]),
+                    )]),
+                    Value::Map(vec![(
+                        Value::from("Moved"),
+                        Value::Array(vec![
+                            Value::from("Point"),
+                            Value::Array(vec![Value::from(6), Value::Float(7.5)]),
+                        ]),
+                    )]),
+                ]),
+            ),
+        ]);
+        let mut bytes = vec![];
+        ciborium::into_writer(&value, &mut bytes).unwrap();
+
+        assert_eq!(
+            WebViewCodec::Cbor.decode::<Message>(&bytes),
+            Ok(Message {
+                x: 1.0,
+                y: -2.0,
+                count: 3,
+                offset: -4,
+                name: None,
+                shapes: vec![
+                    Shape::Point,
+                    Shape::Circle { radius: 5.0 },
+                    Shape::Moved(Box::new(Shape::Point), [6.0, 7.5]),
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn cbor_round_trips() {
+        let msg = Message {
+            x: 0.5,
+            y: f32::INFINITY,
+            count: u32::MAX,
+            offset: i64::MIN,
+            name: Some("bevy".to_owned()),
+            shapes: vec![Shape::Moved(
+                Box::new(Shape::Circle { radius: 1.5 }),
+                [-0.0, 1e300],
+            )],
+        };
+        let bytes = WebViewCodec::Cbor.encode(&msg).unwrap();
+        assert_eq!(WebViewCodec::Cbor.decode::<Message>(&bytes), Ok(msg));
+        let bytes = WebViewCodec::Cbor.encode(&256).unwrap();
+        assert!(WebViewCodec::Cbor.decode::<u8>(&bytes).is_err());
+    }
+
+    /// JSON for people, in another name
+    struct PrettyJson;
+
+    impl IpcCodec for PrettyJson {
+        fn name(&self) -> &str {
+            "pretty"
+        }
+
+        fn is_binary(&self) -> bool {
+            false
+        }
+
+        fn script(&self) -> Option<&str> {
+            Some("window.__bevy.codecs.pretty = window.__bevy.codecs.json;")
+        }
+
+        fn encode(&self, msg: &dyn erased_serde::Serialize) -> Result<Vec<u8>, String> {
+            serde_json::to_vec_pretty(msg).map_err(|e| e.to_string())
+        }
+
+        fn decode<'de>(&self, bytes: &'de [u8], visit: Visit<'_, 'de>) -> Result<(), String> {
+            JsonCodec.decode(bytes, visit)
+        }
+    }
+
+    #[test]
+    fn custom_codecs() {
+        let codec = WebViewCodec::custom(PrettyJson);
+        let msg = Shape::Circle { radius: 1.5 };
+        let bytes = codec.encode(&msg).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes.clone()).unwrap(),
+            "{\n  \"Circle\": {\n    \"radius\": 1.5\n  }\n}"
+        );
+        assert_eq!(codec.decode::<Shape>(&bytes), Ok(msg));
+        assert!(codec.decode::<Shape>(b"\"Point\" trailing").is_err());
+
+        assert_eq!(codec.name(), "pretty");
+        assert_eq!(codec, codec.clone());
+        assert_ne!(codec, WebViewCodec::custom(PrettyJson));
+        assert_ne!(codec, WebViewCodec::Json);
+        assert_eq!(WebViewCodec::Json.script(), "");
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// Says what an IPC frame carries
+///
+/// A frame is its header as a line of JSON followed by the payload as encoded by the webview's
+/// `WebViewCodec`. The page side of this lives in `assets/bevy.js`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum Header {
+    /// A plain message, for `IpcQueue` or the page's `bevy.on("message", ...)`
+    Message,
+    /// A call of the handler registered as `name`, answered by the `Reply` with the same `id`
+    Invoke { id: u64, name: String },
+    /// The outcome of call `id`; the payload is the handler's error unless `ok`
+    Reply { id: u64, ok: bool },
+    /// The page wants messages published to `topic`
+    Subscribe { topic: String },
+    /// The page no longer wants messages published to `topic`
+    Unsubscribe { topic: String },
+    /// A message for the subscribers of `topic`
+    Publish { topic: String },
+    /// The page runs a new `document`, e.g. after a reload; it's sent before any other frame
+    Load { document: String },
+    /// The page's `document` is loaded and listening; frames for it are held back until then
+    Ready { document: String },
+}
+
+pub(crate) fn encode(header: &Header, payload: &[u8]) -> Vec<u8> {
+    let mut frame = serde_json::to_vec(header).expect("IPC headers always serialize");
+    frame.push(b'\n');
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Joins frames for a single fetch of the page, each prefixed with its length as a big endian `u32`
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+)))]
+pub(crate) fn batch(frames: impl Iterator<Item = Vec<u8>>) -> Vec<u8> {
+    frames.fold(vec![], |mut batch, frame| {
+        batch.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        batch.extend_from_slice(&frame);
+        batch
+    })
+}
+
+pub(crate) fn decode(frame: &[u8]) -> Option<(Header, &[u8])> {
+    // Serialized JSON never contains a raw newline, so the first one ends the header
+    let split = frame.iter().position(|&x| x == b'\n')?;
+    let header = serde_json::from_slice(&frame[..split]).ok()?;
+    Some((header, &frame[split + 1..]))
+}
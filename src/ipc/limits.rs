@@ -0,0 +1,278 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use bevy::prelude::*;
+
+use super::{IpcChannel, WebViewIpcPlugin};
+
+/// Bounds the queues of a webview's IPC channel
+#[derive(Component, Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WebViewIpcLimits {
+    /// Messages that may wait in each direction; at least 1
+    pub capacity: usize,
+    /// What happens to messages arriving while `capacity` are waiting
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for WebViewIpcLimits {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the message that waited longest to make room
+    #[default]
+    DropOldest,
+    /// Drop the arriving message
+    DropNewest,
+    /// Keep only the last waiting message of each type and of each topic, for messages that
+    /// each carry a complete state; if they still don't fit, drop the one that waited longest
+    Coalesce,
+}
+
+/// Depth and losses of a webview's IPC queues, updated every frame
+///
+/// Replies to the page's `window.bevy.invoke` calls are never dropped, as the page waits for
+/// them; they count towards `outgoing_depth` but not `capacity`.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WebViewIpcMetrics {
+    /// Messages and calls from the page waiting to be handled
+    pub incoming_depth: usize,
+    /// Messages waiting to be delivered to the page, e.g. until it's ready
+    pub outgoing_depth: usize,
+    /// Messages and calls from the page dropped by the `OverflowPolicy`
+    pub incoming_dropped: usize,
+    /// Messages for the page dropped by the `OverflowPolicy`
+    pub outgoing_dropped: usize,
+}
+
+/// What `OverflowPolicy::Coalesce` keeps the last one of
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum CoalesceKey {
+    /// A message of this `type`; messages without a type share one
+    Message(Option<String>),
+    /// A publish to this topic
+    Topic(String),
+}
+
+/// One direction of an IPC channel, bounded by `WebViewIpcLimits`
+///
+/// Pushing never waits for room: senders are Bevy systems and the webview's event loop, which
+/// must not stall.
+pub(crate) struct Queue<M> {
+    /// Waiting messages, the oldest first; those without a key are never coalesced
+    items: Mutex<VecDeque<(Option<CoalesceKey>, M)>>,
+    dropped: AtomicUsize,
+}
+
+impl<M> Default for Queue<M> {
+    fn default() -> Self {
+        Self {
+            items: default(),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<M> Queue<M> {
+    fn items(&self) -> MutexGuard<'_, VecDeque<(Option<CoalesceKey>, M)>> {
+        self.items.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Queues `msg`, returning the messages `limits` dropped to make room, or `msg` itself
+    pub(crate) fn push(&self, msg: M, limits: WebViewIpcLimits) -> Vec<M> {
+        self.push_keyed(msg, None, limits)
+    }
+
+    pub(crate) fn push_keyed(
+        &self,
+        msg: M,
+        key: Option<CoalesceKey>,
+        limits: WebViewIpcLimits,
+    ) -> Vec<M> {
+        let capacity = limits.capacity.max(1);
+        let mut items = self.items();
+        let mut dropped = Vec::new();
+        if items.len() >= capacity {
+            match limits.overflow {
+                OverflowPolicy::DropOldest => {}
+                OverflowPolicy::DropNewest => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return vec![msg];
+                }
+                OverflowPolicy::Coalesce => {
+                    // Walk from the newest, keeping the first of each key seen
+                    let mut seen = key.iter().cloned().collect::<Vec<_>>();
+                    let mut kept = VecDeque::with_capacity(items.len());
+                    while let Some((x, item)) = items.pop_back() {
+                        match x {
+                            Some(x) if seen.contains(&x) => dropped.push(item),
+                            Some(x) => {
+                                seen.push(x.clone());
+                                kept.push_front((Some(x), item));
+                            }
+                            None => kept.push_front((None, item)),
+                        }
+                    }
+                    dropped.reverse();
+                    *items = kept;
+                }
+            }
+            while items.len() >= capacity {
+                dropped.extend(items.pop_front().map(|(_, item)| item));
+            }
+            self.dropped.fetch_add(dropped.len(), Ordering::Relaxed);
+        }
+        items.push_back((key, msg));
+        dropped
+    }
+
+    /// Queues `msg` whatever the limits, for messages that must not be lost
+    pub(crate) fn push_unbounded(&self, msg: M) {
+        self.items().push_back((None, msg));
+    }
+
+    pub(crate) fn pop(&self) -> Option<M> {
+        self.items().pop_front().map(|(_, item)| item)
+    }
+
+    /// Takes every waiting message, the oldest first
+    pub(crate) fn drain(&self) -> impl Iterator<Item = M> {
+        std::mem::take(&mut *self.items())
+            .into_iter()
+            .map(|(_, item)| item)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.items().len()
+    }
+
+    pub(crate) fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl WebViewIpcPlugin {
+    pub(crate) fn on_limits_change(
+        default_limits: Res<WebViewIpcLimits>,
+        mut removed: RemovedComponents<WebViewIpcLimits>,
+        changed: Query<(&WebViewIpcLimits, &IpcChannel), Changed<WebViewIpcLimits>>,
+        defaulted: Query<&IpcChannel, Without<WebViewIpcLimits>>,
+    ) {
+        for (limits, channel) in &changed {
+            channel.set_limits(*limits);
+        }
+        for channel in defaulted.iter_many(removed.read()) {
+            channel.set_limits(*default_limits);
+        }
+        if default_limits.is_changed() {
+            for channel in &defaulted {
+                channel.set_limits(*default_limits);
+            }
+        }
+    }
+
+    pub(crate) fn update_metrics(mut query: Query<(&IpcChannel, &mut WebViewIpcMetrics)>) {
+        for (channel, mut metrics) in &mut query {
+            metrics.set_if_neq(channel.metrics());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(overflow: OverflowPolicy) -> WebViewIpcLimits {
+        WebViewIpcLimits {
+            capacity: 3,
+            overflow,
+        }
+    }
+
+    fn message(ty: &str) -> Option<CoalesceKey> {
+        Some(CoalesceKey::Message(Some(ty.to_owned())))
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let queue = Queue::default();
+        for i in 0..3 {
+            assert!(queue.push(i, limits(OverflowPolicy::DropOldest)).is_empty());
+        }
+        assert_eq!(queue.push(3, limits(OverflowPolicy::DropOldest)), [0]);
+        assert_eq!(queue.push(4, limits(OverflowPolicy::DropOldest)), [1]);
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(queue.drain().collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn drop_newest() {
+        let queue = Queue::default();
+        for i in 0..3 {
+            assert!(queue.push(i, limits(OverflowPolicy::DropNewest)).is_empty());
+        }
+        assert_eq!(queue.push(3, limits(OverflowPolicy::DropNewest)), [3]);
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.drain().collect::<Vec<_>>(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn coalesce_keeps_the_last_of_each_key() {
+        let queue = Queue::default();
+        let coalesce = limits(OverflowPolicy::Coalesce);
+        queue.push_keyed("a1", message("a"), coalesce);
+        queue.push_keyed("b1", message("b"), coalesce);
+        queue.push_keyed("a2", message("a"), coalesce);
+
+        // The queue is full, so every key keeps only its newest message
+        assert_eq!(queue.push_keyed("b2", message("b"), coalesce), ["a1", "b1"]);
+        assert_eq!(queue.drain().collect::<Vec<_>>(), ["a2", "b2"]);
+
+        // Message types and topics don't share keys, and neither do messages without a type
+        queue.push_keyed("x", Some(CoalesceKey::Topic("a".into())), coalesce);
+        queue.push_keyed("untyped", Some(CoalesceKey::Message(None)), coalesce);
+        queue.push_keyed("a", message("a"), coalesce);
+        assert_eq!(queue.push_keyed("a'", message("a"), coalesce), ["a"]);
+        assert_eq!(queue.drain().collect::<Vec<_>>(), ["x", "untyped", "a'"]);
+        assert_eq!(queue.dropped(), 3);
+    }
+
+    #[test]
+    fn coalesce_drops_the_oldest_when_keys_differ() {
+        let queue = Queue::default();
+        let coalesce = limits(OverflowPolicy::Coalesce);
+        queue.push("call", coalesce);
+        queue.push_keyed("a", message("a"), coalesce);
+        queue.push_keyed("b", message("b"), coalesce);
+        assert_eq!(queue.push_keyed("c", message("c"), coalesce), ["call"]);
+        assert_eq!(queue.drain().collect::<Vec<_>>(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn unbounded_pushes_ignore_the_limits() {
+        let queue = Queue::default();
+        let tight = WebViewIpcLimits {
+            capacity: 0,
+            overflow: OverflowPolicy::DropNewest,
+        };
+        queue.push(0, tight);
+        queue.push_unbounded(1);
+        queue.push_unbounded(2);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.push(3, tight), [3]);
+        assert_eq!(queue.pop(), Some(0));
+        assert_eq!(queue.drain().collect::<Vec<_>>(), [1, 2]);
+    }
+}
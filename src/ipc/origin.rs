@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+
+use super::{IpcChannel, WebViewIpcPlugin};
+use crate::{
+    url_pattern::{origin, UrlPatterns},
+    WebViewLocation,
+};
+
+/// Restricts which pages of a webview may talk to Bevy over IPC
+///
+/// Webviews without it trust every page. Pages loaded from the webview's own
+/// `WebViewLocation::Asset` or `WebViewLocation::Html` are always trusted; frames from other
+/// pages are dropped and reported as `WebViewIpcRejected`. Messages for the page are held back
+/// while it isn't trusted.
+///
+/// Pages are told apart by the origin of their requests; frames without an origin of their own,
+/// like sandboxed or `data:` frames, are never trusted.
+///
+/// On Linux, IPC messages carry no origin, so the URL of the webview's top-level page is checked
+/// instead, and every frame of a trusted page is trusted along with it. Don't show remote content
+/// in the same webview as trusted pages there, not even in a frame.
+#[derive(Component, Clone, Debug, Default)]
+pub struct WebViewTrustedOrigins {
+    /// Origins and URL patterns of the pages that may use IPC
+    pub trusted: UrlPatterns,
+}
+
+/// Sent when a page of `entity` that isn't in its `WebViewTrustedOrigins` tried to use IPC
+#[derive(Event, Clone, Debug)]
+pub struct WebViewIpcRejected {
+    pub entity: Entity,
+    /// Origin of the page, or URL of the top-level page on Linux; `None` if it has neither
+    pub origin: Option<String>,
+}
+
+/// The trusted origins as seen by the IPC handlers, which run outside of the `World`
+#[derive(Default)]
+pub(crate) struct OriginRules {
+    pub(crate) trusted: Option<WebViewTrustedOrigins>,
+    /// Origin of the webview's own local pages
+    pub(crate) home: Option<String>,
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    /// URL of the top-level page, as last reported by `wry`
+    pub(crate) page: Option<String>,
+}
+
+impl OriginRules {
+    /// Whether a page at `source` may use IPC; pages without an origin may only if all may
+    pub(crate) fn allows(&self, source: Option<&str>) -> bool {
+        let Some(trusted) = &self.trusted else {
+            return true;
+        };
+        let Some(url) = source else {
+            return false;
+        };
+        let url_origin = origin(url).unwrap_or_else(|| url.to_owned());
+        self.home.as_ref() == Some(&url_origin) || trusted.trusted.matches(url)
+    }
+}
+
+/// Origin of the pages a webview loads from its own `location` rather than from the web
+pub(crate) fn home(location: &WebViewLocation) -> Option<String> {
+    match location {
+        WebViewLocation::Url(_) => None,
+        location => origin(&location.url().ok()?),
+    }
+}
+
+impl WebViewIpcPlugin {
+    pub(crate) fn on_trusted_origins_change(
+        mut removed: RemovedComponents<WebViewTrustedOrigins>,
+        changed: Query<(&WebViewTrustedOrigins, &IpcChannel), Changed<WebViewTrustedOrigins>>,
+        channels: Query<&IpcChannel>,
+    ) {
+        for (trusted, channel) in &changed {
+            channel.set_trusted_origins(Some(trusted.clone()));
+        }
+        for channel in channels.iter_many(removed.read()) {
+            channel.set_trusted_origins(None);
+        }
+    }
+
+    pub(crate) fn report_rejections(
+        channels: Query<(Entity, &IpcChannel)>,
+        mut writer: EventWriter<WebViewIpcRejected>,
+    ) {
+        for (entity, channel) in &channels {
+            writer.send_batch(
+                channel
+                    .rejections()
+                    .map(|origin| WebViewIpcRejected { entity, origin }),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_without_origin_are_untrusted() {
+        let mut rules = OriginRules::default();
+        assert!(rules.allows(None));
+
+        rules.trusted = Some(WebViewTrustedOrigins {
+            trusted: UrlPatterns::default().with("https://example.com"),
+        });
+        rules.home = Some("bevy-asset://localhost".to_owned());
+        assert!(rules.allows(Some("https://example.com")));
+        assert!(rules.allows(Some("bevy-asset://localhost")));
+        assert!(!rules.allows(Some("https://evil.com")));
+        assert!(!rules.allows(None));
+    }
+}
@@ -0,0 +1,87 @@
+use bevy::{prelude::*, utils::HashSet};
+use serde::Serialize;
+
+use super::{IpcChannel, WebViewIpcPlugin};
+
+/// Messages whose last value is replayed to a webview's next document, e.g. after a reload
+///
+/// A message's type is its `type` field, the one `window.bevy.on(type, ...)` listens to.
+#[derive(Component, Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub enum WebViewIpcSticky {
+    /// Nothing is replayed
+    #[default]
+    None,
+    /// The last message of every type; messages without a type share one
+    All,
+    /// The last message of each of these types
+    Types(HashSet<String>),
+}
+
+impl WebViewIpcSticky {
+    pub(crate) fn is_none(&self) -> bool {
+        matches!(self, WebViewIpcSticky::None)
+    }
+
+    pub(crate) fn includes(&self, ty: Option<&str>) -> bool {
+        match self {
+            WebViewIpcSticky::None => false,
+            WebViewIpcSticky::All => true,
+            WebViewIpcSticky::Types(types) => ty.is_some_and(|x| types.contains(x)),
+        }
+    }
+}
+
+/// Sent when a new document replaced the page of `entity`, e.g. on reload or navigation
+///
+/// Listeners and subscriptions of the old page are gone, so resend whatever the new one needs;
+/// it is delivered once the page is ready. Messages the old page hadn't received yet are
+/// dropped, and replaced by the last values kept for `WebViewIpcSticky`.
+///
+/// Loads that leave the old document in place, like downloads or failed loads, don't send this.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct WebViewIpcReset {
+    pub entity: Entity,
+}
+
+/// The `type` of a message, as the page's `window.bevy` client sees it
+pub(crate) fn message_type(msg: &impl Serialize) -> Option<String> {
+    match serde_json::to_value(msg).ok()? {
+        serde_json::Value::Object(mut x) => match x.remove("type")? {
+            serde_json::Value::String(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl WebViewIpcPlugin {
+    pub(crate) fn on_reset(
+        channels: Query<(Entity, &IpcChannel)>,
+        mut writer: EventWriter<WebViewIpcReset>,
+    ) {
+        for (entity, channel) in &channels {
+            if channel.take_was_reset() {
+                writer.send(WebViewIpcReset { entity });
+            }
+        }
+    }
+
+    pub(crate) fn on_sticky_change(
+        default_sticky: Res<WebViewIpcSticky>,
+        mut removed: RemovedComponents<WebViewIpcSticky>,
+        changed: Query<(&WebViewIpcSticky, &IpcChannel), Changed<WebViewIpcSticky>>,
+        defaulted: Query<&IpcChannel, Without<WebViewIpcSticky>>,
+    ) {
+        for (sticky, channel) in &changed {
+            channel.set_sticky(sticky.clone());
+        }
+        for channel in defaulted.iter_many(removed.read()) {
+            channel.set_sticky(default_sticky.clone());
+        }
+        if default_sticky.is_changed() {
+            for channel in &defaulted {
+                channel.set_sticky(default_sticky.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{codec::WebViewCodec, frame::Header, IpcChannel, WebViewIpcPlugin};
+use crate::WebViewHandle;
+
+/// Runs a handler on an encoded request, yielding its encoded response or error
+type Handler =
+    Box<dyn FnMut(&mut World, &WebViewCodec, &[u8]) -> Result<Vec<u8>, Vec<u8>> + Send + Sync>;
+
+/// Handlers of `window.bevy.invoke` calls, by name
+#[derive(Resource, Default)]
+pub(crate) struct WebViewHandlers(HashMap<String, Handler>);
+
+pub trait WebViewRpcExt {
+    /// Answers `window.bevy.invoke(name, args)` from every webview with `handler`
+    ///
+    /// `Ok` resolves the page's promise. `Err`, or `args` that don't decode as `Req`, reject it
+    /// with an `InvokeError` whose `data` is the error.
+    fn add_webview_handler<Req, Res, Err>(
+        &mut self,
+        name: impl Into<String>,
+        handler: impl Fn(Req) -> Result<Res, Err> + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        Req: DeserializeOwned + 'static,
+        Res: Serialize + 'static,
+        Err: Serialize + 'static;
+
+    /// Answers `window.bevy.invoke(name, args)` from every webview by running `system` with
+    /// `In(args)`, e.g. `|In(id): In<u32>, query: Query<&Item>| ...`
+    ///
+    /// The system's output resolves the page's promise; its `Commands` are applied right away.
+    fn add_webview_command<Req, Res, M>(
+        &mut self,
+        name: impl Into<String>,
+        system: impl IntoSystem<Req, Res, M>,
+    ) -> &mut Self
+    where
+        Req: DeserializeOwned + 'static,
+        Res: Serialize + 'static;
+}
+
+impl WebViewRpcExt for App {
+    fn add_webview_handler<Req, Res, Err>(
+        &mut self,
+        name: impl Into<String>,
+        handler: impl Fn(Req) -> Result<Res, Err> + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        Req: DeserializeOwned + 'static,
+        Res: Serialize + 'static,
+        Err: Serialize + 'static,
+    {
+        let name = name.into();
+        let handler: Handler = {
+            let name = name.clone();
+            Box::new(move |_, codec, payload| respond(&name, codec, payload, &handler))
+        };
+        insert_handler(self, name, handler)
+    }
+
+    fn add_webview_command<Req, Res, M>(
+        &mut self,
+        name: impl Into<String>,
+        system: impl IntoSystem<Req, Res, M>,
+    ) -> &mut Self
+    where
+        Req: DeserializeOwned + 'static,
+        Res: Serialize + 'static,
+    {
+        let name = name.into();
+        let mut system = IntoSystem::into_system(system);
+        system.initialize(&mut self.world);
+        let handler: Handler = {
+            let name = name.clone();
+            Box::new(move |world, codec, payload| {
+                respond(&name, codec, payload, |req| {
+                    let res = system.run(req, world);
+                    system.apply_deferred(world);
+                    Ok::<_, ()>(res)
+                })
+            })
+        };
+        insert_handler(self, name, handler)
+    }
+}
+
+fn insert_handler(app: &mut App, name: String, handler: Handler) -> &mut App {
+    app.init_resource::<WebViewHandlers>()
+        .world
+        .resource_mut::<WebViewHandlers>()
+        .0
+        .insert(name, handler);
+    app
+}
+
+/// Decodes the arguments of a call to `name`, runs `f` on them and encodes the outcome
+fn respond<Req, Res, Err>(
+    name: &str,
+    codec: &WebViewCodec,
+    payload: &[u8],
+    f: impl FnOnce(Req) -> Result<Res, Err>,
+) -> Result<Vec<u8>, Vec<u8>>
+where
+    Req: DeserializeOwned,
+    Res: Serialize,
+    Err: Serialize,
+{
+    let req = codec
+        .decode::<Req>(payload)
+        .map_err(|e| error(codec, &format!("invalid arguments to {name}: {e}")))?;
+    match f(req) {
+        Ok(res) => codec
+            .encode(&res)
+            .map_err(|e| error(codec, &format!("invalid response of {name}: {e}"))),
+        Err(err) => Err(codec
+            .encode(&err)
+            .unwrap_or_else(|e| error(codec, &format!("invalid error of {name}: {e}")))),
+    }
+}
+
+/// An error message the page gets instead of the handler's own error
+fn error(codec: &WebViewCodec, message: &str) -> Vec<u8> {
+    codec.encode(&message).unwrap_or_default()
+}
+
+impl WebViewIpcPlugin {
+    /// Runs the handlers of the calls pages made since the last frame
+    pub(crate) fn answer_calls(
+        world: &mut World,
+        channels: &mut QueryState<(&WebViewHandle, &IpcChannel)>,
+    ) {
+        let channels = channels
+            .iter(world)
+            .map(|(handle, channel)| (*handle, channel.clone()))
+            .collect::<Vec<_>>();
+
+        world.resource_scope(|world, mut handlers: Mut<WebViewHandlers>| {
+            for (handle, channel) in channels {
+                let codec = channel.codec();
+                // The page would otherwise wait for these until its call times out
+                for call in channel.overflowed_calls() {
+                    let payload = error(
+                        &codec,
+                        &format!("{} was dropped, too many calls were waiting", call.name),
+                    );
+                    world.send_event(channel.push(
+                        handle,
+                        &Header::Reply {
+                            id: call.id,
+                            ok: false,
+                        },
+                        &payload,
+                    ));
+                }
+                for call in channel.calls() {
+                    let reply = match handlers.0.get_mut(&call.name) {
+                        Some(handler) => handler(world, &codec, &call.payload),
+                        None => Err(error(&codec, &format!("no handler named {}", call.name))),
+                    };
+                    let (ok, payload) = match reply {
+                        Ok(x) => (true, x),
+                        Err(x) => (false, x),
+                    };
+                    world.send_event(channel.push(
+                        handle,
+                        &Header::Reply { id: call.id, ok },
+                        &payload,
+                    ));
+                }
+            }
+        });
+    }
+}
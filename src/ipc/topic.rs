@@ -0,0 +1,42 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::Serialize;
+
+use super::{codec::WebViewCodec, frame::Header, FetchEvent, IpcChannel, IpcError};
+use crate::WebViewHandle;
+
+/// Publishes messages to every page subscribed to a topic with `window.bevy.subscribe(topic, cb)`
+///
+/// Unlike `WebViewIpc<T>`, messages of any type can be published to any webview.
+#[derive(SystemParam)]
+pub struct WebViewTopics<'w, 's> {
+    channels: Query<'w, 's, (&'static WebViewHandle, &'static IpcChannel)>,
+    writer: EventWriter<'w, FetchEvent>,
+}
+
+impl WebViewTopics<'_, '_> {
+    /// Sends `msg` to the pages subscribed to `topic`, returning how many there were
+    pub fn publish<M: Serialize>(&mut self, topic: &str, msg: &M) -> Result<usize, IpcError> {
+        let header = Header::Publish {
+            topic: topic.to_owned(),
+        };
+        // Webviews mostly share a codec, so don't encode for each one of them
+        let mut encoded: Option<(WebViewCodec, Vec<u8>)> = None;
+        let mut count = 0;
+        for (handle, channel) in &self.channels {
+            if !channel.is_subscribed(topic) {
+                continue;
+            }
+            let codec = channel.codec();
+            let data = match encoded {
+                Some((ref x, ref data)) if *x == codec => data,
+                _ => {
+                    let data = codec.encode(msg).map_err(IpcError::Encode)?;
+                    &encoded.insert((codec, data)).1
+                }
+            };
+            self.writer.send(channel.push(*handle, &header, data));
+            count += 1;
+        }
+        Ok(count)
+    }
+}
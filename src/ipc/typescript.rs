@@ -0,0 +1,266 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fs, io,
+    path::Path,
+    rc::Rc,
+    sync::Arc,
+};
+
+pub use bevy_wry_webview_macros::TypeScript;
+
+/// A type that can be described to pages as a TypeScript type
+///
+/// Derive it next to `Serialize`/`Deserialize`; `tag`, `content`, `untagged`, `rename`,
+/// `rename_all`, `flatten`, `skip`, `skip_serializing`, `skip_deserializing`, `default` and
+/// `transparent` are followed.
+pub trait TypeScript {
+    /// The TypeScript type of `Self`, declaring the named types it refers to in `decls`
+    fn typescript(decls: &mut TypeScriptDeclarations) -> String;
+}
+
+/// Named types, declared before the types using them
+///
+/// A type may look different in the messages Bevy sends than in those it receives, e.g. with
+/// `skip_serializing`; it's then declared once for each, the second time with an `Incoming` or
+/// `Outgoing` suffix, as the page sees the messages.
+#[derive(Default)]
+pub struct TypeScriptDeclarations {
+    serializing: bool,
+    /// The declared name of each type, in the messages Bevy receives and in those it sends
+    names: [HashMap<String, String>; 2],
+    bodies: HashMap<String, String>,
+    declarations: Vec<String>,
+}
+
+impl TypeScriptDeclarations {
+    /// Whether the types are of messages Bevy sends, rather than of those it receives
+    pub fn serializing(&self) -> bool {
+        self.serializing
+    }
+
+    /// Declares `name` as the type `body` returns, unless it was declared already; returns the
+    /// name it's declared as
+    pub fn declare(&mut self, name: &str, body: impl Fn(&mut Self) -> String) -> String {
+        let side = usize::from(self.serializing);
+        if let Some(declared) = self.names[side].get(name) {
+            return declared.clone();
+        }
+
+        // Types that look the same both ways are declared once
+        let mut declared = name.to_owned();
+        if let Some(other) = self.names[1 - side].get(name).cloned() {
+            self.names[side].insert(name.to_owned(), other.clone());
+            let same = body(self);
+            if self.bodies.get(&other) == Some(&same) {
+                return other;
+            }
+            declared += if self.serializing {
+                "Incoming"
+            } else {
+                "Outgoing"
+            };
+        }
+
+        // Inserted before `body` runs, so recursive types refer to themselves by name
+        self.names[side].insert(name.to_owned(), declared.clone());
+        let body = body(self);
+        self.declarations
+            .push(format!("export type {declared} = {body};\n"));
+        self.bodies.insert(declared.clone(), body);
+        declared
+    }
+}
+
+/// `.d.ts` declarations of the messages of a `UiWebViewBundle<T, U>`, typing the `window.bevy`
+/// client of its pages
+pub fn declarations<T: TypeScript, U: TypeScript>() -> String {
+    let mut decls = TypeScriptDeclarations {
+        serializing: true,
+        ..Default::default()
+    };
+    let incoming = T::typescript(&mut decls);
+    decls.serializing = false;
+    let outgoing = U::typescript(&mut decls);
+
+    let mut out = "// Generated by bevy_wry_webview; do not edit\n\n".to_owned();
+    for declaration in decls.declarations {
+        out.push_str(&declaration);
+    }
+    out.push_str(&format!(
+        r#"
+/** Messages Bevy sends to the page */
+export type IncomingMessage = {incoming};
+/** Messages the page sends to Bevy */
+export type OutgoingMessage = {outgoing};
+
+export interface InvokeError extends Error {{
+    command: string;
+    data: unknown;
+}}
+
+export interface BevyClient {{
+    InvokeError: {{ new (command: string, data: unknown): InvokeError }};
+    readonly isReady: boolean;
+    readonly ready: Promise<void>;
+    autoReady: boolean;
+    markReady(): void;
+    on(type: "message", callback: (msg: IncomingMessage) => void): () => void;
+    on(type: "ready", callback: () => void): () => void;
+    on<K extends string>(type: K, callback: (msg: Extract<IncomingMessage, {{ type: K }}>) => void): () => void;
+    off(type: string, callback: (msg?: any) => void): void;
+    send(msg: OutgoingMessage): Promise<void>;
+    invoke<R = unknown>(command: string, args?: unknown, options?: {{ timeout?: number | null }}): Promise<R>;
+    subscribe(topic: string, callback: (msg: any) => void): () => void;
+    unsubscribe(topic: string, callback: (msg: any) => void): void;
+}}
+
+declare global {{
+    interface Window {{
+        bevy: BevyClient;
+        /** @deprecated use `bevy.on("message", ...)` */
+        processMessage: (msg: IncomingMessage) => void;
+        /** @deprecated use `bevy.send` */
+        sendMessage(msg: OutgoingMessage): Promise<void>;
+    }}
+}}
+"#
+    ));
+    out
+}
+
+/// Writes `declarations::<T, U>()` to `path`, e.g. next to the page's sources
+pub fn export_declarations<T: TypeScript, U: TypeScript>(path: impl AsRef<Path>) -> io::Result<()> {
+    fs::write(path, declarations::<T, U>())
+}
+
+#[doc(hidden)]
+pub fn literal(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_default()
+}
+
+#[doc(hidden)]
+pub fn object(properties: &[(&str, bool, String)], flattened: Vec<String>) -> String {
+    let properties = properties
+        .iter()
+        .map(|(key, optional, ty)| {
+            let is_ident = !key.is_empty()
+                && !key.starts_with(|c: char| c.is_ascii_digit())
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+            let key = if is_ident {
+                key.to_string()
+            } else {
+                literal(key)
+            };
+            format!("{key}{}: {ty}", if *optional { "?" } else { "" })
+        })
+        .collect::<Vec<_>>();
+
+    let object = match properties.is_empty() {
+        true if !flattened.is_empty() => None,
+        true => Some("{}".to_owned()),
+        false => Some(format!("{{ {} }}", properties.join("; "))),
+    };
+    object
+        .into_iter()
+        .chain(flattened)
+        .collect::<Vec<_>>()
+        .join(" & ")
+}
+
+#[doc(hidden)]
+pub fn tuple(items: Vec<String>) -> String {
+    format!("[{}]", items.join(", "))
+}
+
+#[doc(hidden)]
+pub fn union(variants: Vec<String>) -> String {
+    match variants.is_empty() {
+        true => "never".to_owned(),
+        false => variants.join(" | "),
+    }
+}
+
+macro_rules! impl_typescript {
+    ($ty:expr => $($t:ty),*) => {
+        $(impl TypeScript for $t {
+            fn typescript(_: &mut TypeScriptDeclarations) -> String {
+                $ty.to_owned()
+            }
+        })*
+    };
+}
+
+impl_typescript!("boolean" => bool);
+impl_typescript!("number" => u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+impl_typescript!("string" => char, String, str);
+impl_typescript!("null" => ());
+impl_typescript!("unknown" => serde_json::Value);
+
+impl<T: TypeScript> TypeScript for Option<T> {
+    fn typescript(decls: &mut TypeScriptDeclarations) -> String {
+        format!("{} | null", T::typescript(decls))
+    }
+}
+
+macro_rules! impl_typescript_wrapper {
+    ($($t:ty),*) => {
+        $(impl<T: TypeScript + ?Sized> TypeScript for $t {
+            fn typescript(decls: &mut TypeScriptDeclarations) -> String {
+                T::typescript(decls)
+            }
+        })*
+    };
+}
+
+impl_typescript_wrapper!(&T, Box<T>, Rc<T>, Arc<T>);
+
+macro_rules! impl_typescript_array {
+    ($($t:ty),*) => {
+        $(impl<T: TypeScript> TypeScript for $t {
+            fn typescript(decls: &mut TypeScriptDeclarations) -> String {
+                let item = T::typescript(decls);
+                match item.contains(' ') {
+                    true => format!("({item})[]"),
+                    false => format!("{item}[]"),
+                }
+            }
+        })*
+    };
+}
+
+impl_typescript_array!([T], Vec<T>, VecDeque<T>, HashSet<T>, BTreeSet<T>);
+
+impl<T: TypeScript, const N: usize> TypeScript for [T; N] {
+    fn typescript(decls: &mut TypeScriptDeclarations) -> String {
+        <[T]>::typescript(decls)
+    }
+}
+
+impl<K: TypeScript, V: TypeScript> TypeScript for HashMap<K, V> {
+    fn typescript(decls: &mut TypeScriptDeclarations) -> String {
+        format!("Record<{}, {}>", K::typescript(decls), V::typescript(decls))
+    }
+}
+
+impl<K: TypeScript, V: TypeScript> TypeScript for BTreeMap<K, V> {
+    fn typescript(decls: &mut TypeScriptDeclarations) -> String {
+        HashMap::<K, V>::typescript(decls)
+    }
+}
+
+macro_rules! impl_typescript_tuple {
+    ($($t:ident),*) => {
+        impl<$($t: TypeScript),*> TypeScript for ($($t,)*) {
+            fn typescript(decls: &mut TypeScriptDeclarations) -> String {
+                tuple(vec![$($t::typescript(decls)),*])
+            }
+        }
+    };
+}
+
+impl_typescript_tuple!(A);
+impl_typescript_tuple!(A, B);
+impl_typescript_tuple!(A, B, C);
+impl_typescript_tuple!(A, B, C, D);
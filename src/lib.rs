@@ -1,35 +1,111 @@
-use ipc::{new_ipc_channel, FetchEvent, IpcQueue, IpcSender, TemporaryIpcStore, WebViewIpcPlugin};
+use asset_protocol::{asset_url, inline_html_url, make_asset_protocol, InlineHtml, ASSET_PROTOCOL};
+use ipc::{
+    codec::WebViewCodec,
+    limits::{WebViewIpcLimits, WebViewIpcMetrics},
+    new_ipc_channel,
+    origin::{self, WebViewTrustedOrigins},
+    reset::WebViewIpcSticky,
+    FetchEvent, IpcChannel, IpcQueue, IpcSender, TemporaryIpcStore, WebViewIpcPlugin,
+};
+use lifecycle::{
+    LifecycleChannel, WebViewCreated, WebViewCurrentUrl, WebViewError, WebViewFailed,
+    WebViewLifecyclePlugin, WebViewState,
+};
+use navigation::{NavigationGuard, WebViewNavigationPlugin, WebViewNavigationPolicy};
 use reactivity::WebViewReactivityPlugin;
 use wry::{WebView, WebViewBuilder};
 
 use bevy::{
     prelude::*,
-    window::{RawHandleWrapper, WindowResized},
+    utils::{HashMap, HashSet},
+    window::{PrimaryWindow, RawHandleWrapper, WindowResized},
 };
 use raw_window_handle::{ActiveHandle, WindowHandle};
 use serde::{Deserialize, Serialize};
+use slotmap::{new_key_type, SlotMap};
 
+mod asset_protocol;
 pub mod ipc;
+pub mod lifecycle;
+pub mod navigation;
 mod reactivity;
+pub mod url_pattern;
 
+/// `WebViewCodec`, `WebViewIpcLimits` and `WebViewIpcSticky` are set per webview by inserting
+/// them next to its `UiWebViewBundle`. Webviews without one of their own use the resource of that
+/// type, which can be inserted before or after adding the plugin. Changes to any of them apply
+/// right away, except that the codec of a webview is fixed once it is spawned.
 pub struct WebViewPlugin;
 
 #[derive(Component, Debug)]
 pub enum WebViewLocation {
     Url(String),
+    /// A page served next to the default asset source's root, so relative links load from the
+    /// assets; changing it loads a fresh document, like a reload
     Html(String),
+    /// A path resolved through Bevy's asset sources, e.g. `ui/index.html` or
+    /// `embedded://my_crate/ui/index.html`; relative links load from the same source
+    Asset(String),
+}
+
+impl WebViewLocation {
+    /// The URL this location loads; HTML is served by the webview's asset protocol
+    pub(crate) fn url(&self) -> Result<String, WebViewError> {
+        match self {
+            WebViewLocation::Url(url) => Ok(url.clone()),
+            WebViewLocation::Asset(path) => asset_url(path),
+            WebViewLocation::Html(_) => Ok(inline_html_url()),
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct WebViewMarker;
 
-#[derive(DerefMut, Deref)]
+/// The `Window` entity a webview is parented to; webviews without it use the `PrimaryWindow`
+#[derive(Component, Clone, Copy, Deref, DerefMut, PartialEq, Eq, Debug)]
+pub struct WebViewTarget(pub Entity);
+
+impl WebViewTarget {
+    /// Resolves the window a webview lives in, falling back to the primary window
+    pub(crate) fn resolve(target: Option<&Self>, primary: Option<Entity>) -> Option<Entity> {
+        target.map(|x| x.0).or(primary)
+    }
+}
+
+new_key_type! {
+    /// Generational key of a `wry` `WebView` inside the `WebViewRegistry`
+    pub struct WebViewKey;
+}
+
+/// Owns every native `WebView`; removing an entry destroys the native webview and frees its slot
+#[derive(DerefMut, Deref, Default)]
 pub struct WebViewRegistry {
-    webviews: Vec<WebView>,
+    #[deref]
+    webviews: SlotMap<WebViewKey, WebView>,
+    owners: HashMap<Entity, WebViewKey>,
 }
 
+impl WebViewRegistry {
+    fn insert_owned(&mut self, owner: Entity, webview: WebView) -> WebViewKey {
+        let key = self.webviews.insert(webview);
+        if let Some(old) = self.owners.insert(owner, key) {
+            self.webviews.remove(old);
+        }
+        key
+    }
+
+    /// Removes, and thereby destroys, the `WebView` spawned for `owner`
+    pub fn remove_owned(&mut self, owner: Entity) -> Option<WebView> {
+        self.owners
+            .remove(&owner)
+            .and_then(|key| self.webviews.remove(key))
+    }
+}
+
+/// Resolves to `None` once the webview it pointed to has been removed from the `WebViewRegistry`
 #[derive(Component, Clone, Copy, Deref, DerefMut, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
-pub struct WebViewHandle(Option<usize>);
+pub struct WebViewHandle(Option<WebViewKey>);
 
 #[derive(Bundle)]
 pub struct UiWebViewBundle<T, U>
@@ -41,6 +117,8 @@ where
     pub location: WebViewLocation,
     pub handle: WebViewHandle,
     pub marker: WebViewMarker,
+    pub state: WebViewState,
+    pub current_url: WebViewCurrentUrl,
     pub ipc_sender: IpcSender<T>,
     pub ipc_queue: IpcQueue<U>,
     pub temporary_ipc_store: TemporaryIpcStore,
@@ -58,6 +136,8 @@ where
             location: WebViewLocation::Html("".to_owned()),
             handle: WebViewHandle(None),
             marker: WebViewMarker,
+            state: WebViewState::Pending,
+            current_url: default(),
             ipc_sender,
             ipc_queue,
             temporary_ipc_store,
@@ -67,6 +147,8 @@ where
 
 /**
  * A simple trait to emulate a custom command for despawning `UiWebViewBundle`s
+ *
+ * Plain `despawn`/`despawn_recursive` also clean up the `WebView`; this only does so immediately
  */
 pub trait WebViewDespawning {
     /**
@@ -78,14 +160,13 @@ pub trait WebViewDespawning {
 impl WebViewDespawning for Commands<'_, '_> {
     fn despawn_webview(&mut self, entity: Entity) {
         self.add(move |world: &mut World| {
-            let registry = world
-                .get_non_send_resource::<WebViewRegistry>()
+            let mut registry = world
+                .get_non_send_resource_mut::<WebViewRegistry>()
                 .unwrap_or_else(|| {
                     panic!("WebView Registry not found; have you loaded `WebViewPlugin`")
                 });
-            let handle = world.entity(entity).get::<WebViewHandle>().unwrap();
-            // TODO close it here -- Waiting on Tauri/Wry folks
-            handle.map(|x| registry.get(x)).map(drop);
+            // Dropping the `WebView` tears down the native webview
+            registry.remove_owned(entity);
             world.despawn(entity);
         })
     }
@@ -123,9 +204,24 @@ impl Plugin for WebViewPlugin {
             target_os = "netbsd",
             target_os = "openbsd",
         )))]
-        app.insert_non_send_resource(WebViewRegistry { webviews: vec![] })
-            .add_plugins((WebViewReactivityPlugin, WebViewIpcPlugin))
-            .add_systems(Update, (Self::on_webview_spawn, Self::handle_fetch));
+        app.init_non_send_resource::<WebViewRegistry>()
+            .init_resource::<WebViewCodec>()
+            .init_resource::<WebViewIpcLimits>()
+            .init_resource::<WebViewIpcSticky>()
+            .add_plugins((
+                WebViewReactivityPlugin,
+                WebViewIpcPlugin,
+                WebViewLifecyclePlugin,
+                WebViewNavigationPlugin,
+            ))
+            .add_systems(
+                Update,
+                (
+                    Self::on_webview_spawn,
+                    Self::on_webview_despawn,
+                    Self::handle_fetch,
+                ),
+            );
 
         #[cfg(any(
             target_os = "linux",
@@ -134,12 +230,21 @@ impl Plugin for WebViewPlugin {
             target_os = "netbsd",
             target_os = "openbsd",
         ))]
-        app.insert_non_send_resource(WebViewRegistry { webviews: vec![] })
-            .add_plugins((WebViewReactivityPlugin, WebViewIpcPlugin))
+        app.init_non_send_resource::<WebViewRegistry>()
+            .init_resource::<WebViewCodec>()
+            .init_resource::<WebViewIpcLimits>()
+            .init_resource::<WebViewIpcSticky>()
+            .add_plugins((
+                WebViewReactivityPlugin,
+                WebViewIpcPlugin,
+                WebViewLifecyclePlugin,
+                WebViewNavigationPlugin,
+            ))
             .add_systems(
                 Update,
                 (
                     Self::on_webview_spawn,
+                    Self::on_webview_despawn,
                     Self::handle_fetch,
                     Self::forward_gtk,
                 ),
@@ -148,129 +253,258 @@ impl Plugin for WebViewPlugin {
 }
 
 impl WebViewPlugin {
+    #[allow(clippy::too_many_arguments)]
     fn on_webview_spawn(
         mut commands: Commands,
         mut registry: NonSendMut<WebViewRegistry>,
-        window_handle: Query<&RawHandleWrapper>,
+        lifecycle: Res<LifecycleChannel>,
+        default_codec: Res<WebViewCodec>,
+        default_limits: Res<WebViewIpcLimits>,
+        default_sticky: Res<WebViewIpcSticky>,
+        asset_server: Res<AssetServer>,
+        mut created: EventWriter<WebViewCreated>,
+        mut failed: EventWriter<WebViewFailed>,
+        window_handles: Query<&RawHandleWrapper>,
+        primary_window: Query<Entity, With<PrimaryWindow>>,
         mut query: Query<
             (
                 Entity,
                 &mut WebViewHandle,
+                &mut WebViewState,
                 &WebViewLocation,
                 &Node,
                 &GlobalTransform,
                 &TemporaryIpcStore,
+                (
+                    Option<&WebViewTarget>,
+                    Option<&WebViewNavigationPolicy>,
+                    Option<&WebViewCodec>,
+                    Option<&WebViewIpcLimits>,
+                    Option<&WebViewIpcSticky>,
+                    Option<&WebViewTrustedOrigins>,
+                ),
             ),
             With<WebViewMarker>,
         >,
     ) {
-        if let Ok(window_handle) = window_handle.get_single().map(|x| x.window_handle) {
-            for (entity, mut handle, location, size, position, tis) in
-                query.iter_mut().filter(|(_, x, _, _, _, _)| x.is_none())
-            // && v.is_visible())
-            {
-                let size = size.size();
-                let final_position = (
-                    (position.translation().x - size.x / 2.0) as i32,
-                    (position.translation().y - size.y / 2.0) as i32,
+        let primary_window = primary_window.get_single().ok();
+        for (
+            entity,
+            mut handle,
+            mut state,
+            location,
+            size,
+            position,
+            tis,
+            (target, policy, codec, limits, sticky, trusted),
+        ) in query.iter_mut().filter(|(_, x, state, _, _, _, _, _)| {
+            x.is_none() && !matches!(**state, WebViewState::Failed(_))
+        })
+        // && v.is_visible())
+        {
+            let Some(window_handle) = WebViewTarget::resolve(target, primary_window)
+                .and_then(|x| window_handles.get(x).ok())
+                .map(|x| x.window_handle)
+            else {
+                continue;
+            };
+
+            let codec = codec.unwrap_or(&default_codec).clone();
+            tis.set_limits(limits.copied().unwrap_or(*default_limits));
+            tis.set_sticky(sticky.unwrap_or(&default_sticky).clone());
+            // Last, as it queues the messages sent so far
+            tis.set_codec(codec.clone());
+            tis.set_trusted_origins(trusted.cloned(), origin::home(location));
+
+            let size = size.size();
+            let final_position = (
+                (position.translation().x - size.x / 2.0) as i32,
+                (position.translation().y - size.y / 2.0) as i32,
+            );
+
+            let borrowed_handle =
+                unsafe { &WindowHandle::borrow_raw(window_handle, ActiveHandle::new()) };
+
+            #[cfg(not(any(
+                target_os = "linux",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "openbsd",
+            )))]
+            let webview = {
+                let func = tis.clone().make_async_protocol();
+                WebViewBuilder::new_as_child(&borrowed_handle)
+                    .with_position(final_position)
+                    .with_transparent(true)
+                    .with_size((size.x as u32, size.y as u32))
+                    .with_initialization_script(&format!(
+                        "window.__bevy = {{ codec: {:?}, isWindows: {} }}",
+                        codec.name(),
+                        cfg!(target_os = "windows")
+                    ))
+                    .with_initialization_script(include_str!("../assets/msgpack.min.js"))
+                    .with_initialization_script(include_str!("../assets/cbor.js"))
+                    .with_initialization_script(include_str!("../assets/codecs.js"))
+                    .with_initialization_script(codec.script())
+                    .with_initialization_script(include_str!("../assets/bevy.js"))
+                    .with_initialization_script(include_str!("../assets/init.js"))
+                    .with_asynchronous_custom_protocol(
+                        "bevy".to_owned(),
+                        func, //WebViewIpcPlugin::handle_ipc,
+                    )
+            };
+
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "openbsd",
+            ))]
+            let webview = {
+                let func = tis.clone().make_ipc_handler();
+                WebViewBuilder::new(&borrowed_handle)
+                    .with_position(final_position)
+                    .with_transparent(true)
+                    .with_size((size.x as u32, size.y as u32))
+                    .with_initialization_script(&format!(
+                        "window.__bevy = {{ codec: {:?} }}",
+                        codec.name()
+                    ))
+                    .with_initialization_script(include_str!("../assets/msgpack.min.js"))
+                    .with_initialization_script(include_str!("../assets/cbor.js"))
+                    .with_initialization_script(include_str!("../assets/codecs.js"))
+                    .with_initialization_script(codec.script())
+                    .with_initialization_script(include_str!("../assets/bevy.js"))
+                    .with_initialization_script(include_str!("../assets/init_linux.js"))
+                    .with_ipc_handler(func)
+            };
+
+            let url = location.url();
+            let guard = NavigationGuard::new(policy, url.clone().ok());
+            let html = InlineHtml::default();
+            if let WebViewLocation::Html(x) = location {
+                html.set(x);
+            }
+            let on_page_load = lifecycle.make_page_load_handler(entity);
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "openbsd",
+            ))]
+            let channel = tis.channel();
+            let webview = webview
+                .with_asynchronous_custom_protocol(
+                    ASSET_PROTOCOL.to_owned(),
+                    make_asset_protocol(asset_server.clone(), html.clone()),
+                )
+                .with_on_page_load_handler(move |event, url| {
+                    #[cfg(any(
+                        target_os = "linux",
+                        target_os = "dragonfly",
+                        target_os = "freebsd",
+                        target_os = "netbsd",
+                        target_os = "openbsd",
+                    ))]
+                    channel.set_page(&url);
+                    on_page_load(event, url)
+                })
+                .with_navigation_handler(lifecycle.make_navigation_handler(entity, guard.clone()))
+                .with_new_window_req_handler(
+                    lifecycle.make_new_window_handler(entity, guard.clone()),
                 );
 
-                *handle = WebViewHandle(Some(registry.len()));
-
-                let borrowed_handle =
-                    unsafe { &WindowHandle::borrow_raw(window_handle, ActiveHandle::new()) };
-
-                #[cfg(not(any(
-                    target_os = "linux",
-                    target_os = "dragonfly",
-                    target_os = "freebsd",
-                    target_os = "netbsd",
-                    target_os = "openbsd",
-                )))]
-                let webview = {
-                    let func = tis.clone().make_async_protocol();
-                    WebViewBuilder::new_as_child(&borrowed_handle)
-                        .with_position(final_position)
-                        .with_transparent(true)
-                        .with_size((size.x as u32, size.y as u32))
-                        .with_initialization_script(&format!(
-                            "let isWindows = {}",
-                            cfg!(target_os = "windows")
-                        ))
-                        .with_initialization_script(include_str!("../assets/msgpack.min.js"))
-                        .with_initialization_script(include_str!("../assets/init.js"))
-                        .with_asynchronous_custom_protocol(
-                            "bevy".to_owned(),
-                            func, //WebViewIpcPlugin::handle_ipc,
-                        )
-                };
-
-                #[cfg(any(
-                    target_os = "linux",
-                    target_os = "dragonfly",
-                    target_os = "freebsd",
-                    target_os = "netbsd",
-                    target_os = "openbsd",
-                ))]
-                let webview = {
-                    let func = tis.clone().make_ipc_handler();
-                    WebViewBuilder::new(&borrowed_handle)
-                        .with_position(final_position)
-                        .with_transparent(true)
-                        .with_size((size.x as u32, size.y as u32))
-                        .with_initialization_script(include_str!("../assets/init_linux.js"))
-                        .with_ipc_handler(func)
-                };
-
-                let webview = match location {
-                    WebViewLocation::Url(url) => webview.with_url(url),
-                    WebViewLocation::Html(html) => webview.with_html(html),
-                }
-                .unwrap()
-                .build()
-                .unwrap();
+            let webview = url
+                .and_then(|url| {
+                    webview
+                        .with_url(&url)
+                        .map_err(|e| WebViewError::InvalidLocation(e.to_string()))
+                })
+                .and_then(|x| x.build().map_err(|e| WebViewError::Build(e.to_string())));
 
-                if let Some(mut x) = commands.get_entity(entity) {
-                    x.remove::<TemporaryIpcStore>();
+            let webview = match webview {
+                Ok(webview) => webview,
+                Err(error) => {
+                    warn!("Could not create webview for {entity:?}: {error}");
+                    *state = WebViewState::Failed(error.clone());
+                    failed.send(WebViewFailed { entity, error });
+                    continue;
                 }
+            };
 
-                registry.push(webview);
+            if let Some(mut x) = commands.get_entity(entity) {
+                x.remove::<TemporaryIpcStore>().insert((
+                    guard,
+                    html,
+                    tis.channel(),
+                    WebViewIpcMetrics::default(),
+                ));
             }
+
+            *handle = WebViewHandle(Some(registry.insert_owned(entity, webview)));
+            *state = WebViewState::Loading;
+            created.send(WebViewCreated { entity });
         }
     }
 
-    #[cfg(not(any(
-        target_os = "linux",
-        target_os = "dragonfly",
-        target_os = "freebsd",
-        target_os = "netbsd",
-        target_os = "openbsd",
-    )))]
-    fn handle_fetch(registry: NonSendMut<WebViewRegistry>, mut reader: EventReader<FetchEvent>) {
-        for &i in reader
-            .read()
-            .filter_map(|FetchEvent(WebViewHandle(i))| i.as_ref())
-        {
-            if let Some(wv) = registry.get(i) {
-                let _ = wv.evaluate_script("window.fetchMessage()");
-            }
+    /// Tears down webviews whose entity was despawned or lost its webview components
+    fn on_webview_despawn(
+        mut registry: NonSendMut<WebViewRegistry>,
+        mut removed_markers: RemovedComponents<WebViewMarker>,
+        mut removed_handles: RemovedComponents<WebViewHandle>,
+    ) {
+        for entity in removed_markers.read().chain(removed_handles.read()) {
+            registry.remove_owned(entity);
         }
     }
 
-    #[cfg(any(
-        target_os = "linux",
-        target_os = "dragonfly",
-        target_os = "freebsd",
-        target_os = "netbsd",
-        target_os = "openbsd",
-    ))]
-    fn handle_fetch(registry: NonSendMut<WebViewRegistry>, mut reader: EventReader<FetchEvent>) {
-        for (i, j) in reader
+    fn handle_fetch(
+        registry: NonSendMut<WebViewRegistry>,
+        mut reader: EventReader<FetchEvent>,
+        channels: Query<(&WebViewHandle, &IpcChannel)>,
+    ) {
+        // A single delivery picks up everything queued, so one per webview and frame will do
+        let fetched = reader
             .read()
-            .filter_map(|FetchEvent(WebViewHandle(i), j)| Some((i.clone(), j.clone())))
-        {
-            if let Some(wv) = i.and_then(|i| registry.get(i)) {
-                let _ = wv.evaluate_script(&format!(r#"window.fetchMessage(`{}`)"#, j));
+            .filter_map(|FetchEvent(WebViewHandle(i))| *i)
+            .collect::<HashSet<_>>();
+        if fetched.is_empty() {
+            return;
+        }
+
+        for (WebViewHandle(i), channel) in &channels {
+            // Frames for pages that aren't ready yet wait for `WebViewIpcReady`
+            let Some(i) = i.filter(|i| fetched.contains(i) && channel.is_ready()) else {
+                continue;
+            };
+            let Some(wv) = registry.get(i) else {
+                continue;
+            };
+
+            #[cfg(not(any(
+                target_os = "linux",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "openbsd",
+            )))]
+            let _ = wv.evaluate_script("window.__bevy.fetch()");
+
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "openbsd",
+            ))]
+            {
+                let script = channel.take_fetch_script();
+                if !script.is_empty() {
+                    let _ = wv.evaluate_script(&script);
+                }
             }
         }
     }
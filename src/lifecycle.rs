@@ -0,0 +1,218 @@
+use std::fmt;
+
+use bevy::prelude::*;
+use wry::PageLoadEvent;
+
+use crate::navigation::{NavigationGuard, WebViewNavigationBlocked, WebViewNewWindowRequested};
+
+pub(crate) struct WebViewLifecyclePlugin;
+
+impl Plugin for WebViewLifecyclePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LifecycleChannel>()
+            .add_event::<WebViewCreated>()
+            .add_event::<WebViewFailed>()
+            .add_event::<WebViewNavigated>()
+            .add_event::<WebViewLoadStarted>()
+            .add_event::<WebViewLoadFinished>()
+            .add_systems(Update, Self::on_page_event);
+    }
+}
+
+/// Where a webview is in its life; maintained by `WebViewPlugin`
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub enum WebViewState {
+    /// Waiting for a window to be parented to
+    #[default]
+    Pending,
+    /// The native webview exists and its page is loading
+    Loading,
+    /// The page finished loading
+    Ready,
+    /// The native webview could not be created; it's retried once its `WebViewLocation` changes
+    Failed(WebViewError),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebViewError {
+    /// `wry` rejected the `WebViewLocation`
+    InvalidLocation(String),
+    /// The native webview could not be built
+    Build(String),
+}
+
+impl fmt::Display for WebViewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebViewError::InvalidLocation(e) => write!(f, "invalid webview location: {e}"),
+            WebViewError::Build(e) => write!(f, "failed to build webview: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for WebViewError {}
+
+/// Sent once the native webview of `entity` has been built
+#[derive(Event, Clone, Debug)]
+pub struct WebViewCreated {
+    pub entity: Entity,
+}
+
+/// Sent when the native webview of `entity` could not be created, or a new `WebViewLocation` of
+/// it could not be loaded
+#[derive(Event, Clone, Debug)]
+pub struct WebViewFailed {
+    pub entity: Entity,
+    pub error: WebViewError,
+}
+
+/// The URL of the page currently shown by a webview; empty until its first page load
+#[derive(Component, Clone, Debug, Default, Deref, PartialEq, Eq)]
+pub struct WebViewCurrentUrl(pub(crate) String);
+
+/// Sent when the webview of `entity` navigates to `url`, e.g. after a link click
+#[derive(Event, Clone, Debug)]
+pub struct WebViewNavigated {
+    pub entity: Entity,
+    pub url: String,
+}
+
+/// Sent when the webview of `entity` starts loading `url`
+#[derive(Event, Clone, Debug)]
+pub struct WebViewLoadStarted {
+    pub entity: Entity,
+    pub url: String,
+}
+
+/// Sent when the webview of `entity` finished loading `url`
+#[derive(Event, Clone, Debug)]
+pub struct WebViewLoadFinished {
+    pub entity: Entity,
+    pub url: String,
+}
+
+/// Notifications coming out of `wry`'s callbacks, which can't touch the `World` directly
+pub(crate) enum PageEvent {
+    Navigated(String),
+    NavigationBlocked { url: String, new_window: bool },
+    NewWindowRequested(String),
+    LoadStarted(String),
+    LoadFinished(String),
+}
+
+#[derive(Resource)]
+pub(crate) struct LifecycleChannel {
+    sender: crossbeam::Sender<(Entity, PageEvent)>,
+    receiver: crossbeam::Receiver<(Entity, PageEvent)>,
+}
+
+impl Default for LifecycleChannel {
+    fn default() -> Self {
+        let (sender, receiver) = crossbeam::unbounded();
+        Self { sender, receiver }
+    }
+}
+
+impl LifecycleChannel {
+    pub(crate) fn make_page_load_handler(
+        &self,
+        entity: Entity,
+    ) -> impl Fn(PageLoadEvent, String) + 'static {
+        let sender = self.sender.clone();
+        move |event, url| {
+            let _ = sender.send((
+                entity,
+                match event {
+                    PageLoadEvent::Started => PageEvent::LoadStarted(url),
+                    PageLoadEvent::Finished => PageEvent::LoadFinished(url),
+                },
+            ));
+        }
+    }
+
+    pub(crate) fn make_navigation_handler(
+        &self,
+        entity: Entity,
+        guard: NavigationGuard,
+    ) -> impl Fn(String) -> bool + 'static {
+        let sender = self.sender.clone();
+        move |url| {
+            let allowed = guard.allows(&url);
+            let _ = sender.send((
+                entity,
+                if allowed {
+                    PageEvent::Navigated(url)
+                } else {
+                    PageEvent::NavigationBlocked {
+                        url,
+                        new_window: false,
+                    }
+                },
+            ));
+            allowed
+        }
+    }
+
+    pub(crate) fn make_new_window_handler(
+        &self,
+        entity: Entity,
+        guard: NavigationGuard,
+    ) -> impl Fn(String) -> bool + 'static {
+        let sender = self.sender.clone();
+        // Popups never get a native window of their own; they are surfaced to the ECS instead
+        move |url| {
+            let event = if guard.allows(&url) {
+                PageEvent::NewWindowRequested(url)
+            } else {
+                PageEvent::NavigationBlocked {
+                    url,
+                    new_window: true,
+                }
+            };
+            let _ = sender.send((entity, event));
+            false
+        }
+    }
+}
+
+impl WebViewLifecyclePlugin {
+    fn on_page_event(
+        channel: Res<LifecycleChannel>,
+        mut navigated: EventWriter<WebViewNavigated>,
+        mut blocked: EventWriter<WebViewNavigationBlocked>,
+        mut new_window: EventWriter<WebViewNewWindowRequested>,
+        mut load_started: EventWriter<WebViewLoadStarted>,
+        mut load_finished: EventWriter<WebViewLoadFinished>,
+        mut query: Query<(&mut WebViewState, &mut WebViewCurrentUrl)>,
+    ) {
+        for (entity, event) in channel.receiver.try_iter() {
+            let Ok((mut state, mut current_url)) = query.get_mut(entity) else {
+                continue;
+            };
+            match event {
+                PageEvent::Navigated(url) => navigated.send(WebViewNavigated { entity, url }),
+                PageEvent::NavigationBlocked { url, new_window } => {
+                    blocked.send(WebViewNavigationBlocked {
+                        entity,
+                        url,
+                        new_window,
+                    })
+                }
+                PageEvent::NewWindowRequested(url) => new_window.send(WebViewNewWindowRequested {
+                    opener: entity,
+                    url,
+                }),
+                PageEvent::LoadStarted(url) => {
+                    *state = WebViewState::Loading;
+                    current_url.set_if_neq(WebViewCurrentUrl(url.clone()));
+                    load_started.send(WebViewLoadStarted { entity, url });
+                }
+                PageEvent::LoadFinished(url) => {
+                    *state = WebViewState::Ready;
+                    current_url.set_if_neq(WebViewCurrentUrl(url.clone()));
+                    load_finished.send(WebViewLoadFinished { entity, url });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    marker::PhantomData,
+    sync::{Arc, RwLock},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ipc::{
+        codec::WebViewCodec, limits::WebViewIpcLimits, origin::WebViewTrustedOrigins,
+        reset::WebViewIpcSticky, IpcQueue, IpcSender,
+    },
+    url_pattern::{self, UrlPatterns},
+    UiWebViewBundle, WebViewLocation, WebViewTarget,
+};
+
+pub(crate) struct WebViewNavigationPlugin;
+
+impl Plugin for WebViewNavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<WebViewNavigationBlocked>()
+            .add_event::<WebViewNewWindowRequested>()
+            .add_systems(Update, Self::on_policy_change);
+    }
+}
+
+/// Restricts where the page of a webview may navigate to, and which new windows it may open
+///
+/// Webviews without a policy may navigate anywhere. Pages from the origin of the webview's own
+/// `WebViewLocation`, and `about:` pages, are always allowed.
+#[derive(Component, Clone, Debug, Default)]
+pub struct WebViewNavigationPolicy {
+    /// Origins and URL patterns pages may navigate to
+    pub allowed: UrlPatterns,
+}
+
+/// Sent when the page of `entity` tried to go to `url` and was denied by its
+/// `WebViewNavigationPolicy`
+#[derive(Event, Clone, Debug)]
+pub struct WebViewNavigationBlocked {
+    pub entity: Entity,
+    pub url: String,
+    /// Whether the page asked for a new window rather than navigating itself
+    pub new_window: bool,
+}
+
+/// Sent when the page of `opener` asks for `url` in a new window, e.g. through a
+/// `target="_blank"` link or `window.open`
+///
+/// No native window is opened; see `WebViewPopupPlugin` to open these as webview entities.
+#[derive(Event, Clone, Debug)]
+pub struct WebViewNewWindowRequested {
+    pub opener: Entity,
+    pub url: String,
+}
+
+/// Opts a webview into having its new-window requests spawned as child webviews by
+/// `WebViewPopupPlugin`
+#[derive(Component, Clone, Debug, Default)]
+pub struct WebViewPopups {
+    /// Position of popups relative to the opener's top left corner
+    pub offset: Vec2,
+    /// Size of popups; defaults to the size of the opener
+    pub size: Option<Vec2>,
+}
+
+/// Marks a webview spawned for a new-window request of `opener`
+#[derive(Component, Clone, Copy, Debug)]
+pub struct WebViewPopup {
+    pub opener: Entity,
+}
+
+/// Spawns a `UiWebViewBundle<T, U>` for every new-window request of a webview with
+/// `WebViewPopups` whose IPC uses the same message types
+pub struct WebViewPopupPlugin<T, U>(PhantomData<fn() -> (T, U)>);
+
+impl<T, U> Default for WebViewPopupPlugin<T, U> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T, U> Plugin for WebViewPopupPlugin<T, U>
+where
+    T: Serialize + Send + Sync + 'static,
+    U: for<'a> Deserialize<'a> + Send + Sync + 'static,
+{
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, Self::spawn_popups);
+    }
+}
+
+impl<T, U> WebViewPopupPlugin<T, U>
+where
+    T: Serialize + Send + Sync + 'static,
+    U: for<'a> Deserialize<'a> + Send + Sync + 'static,
+{
+    fn spawn_popups(
+        mut commands: Commands,
+        mut requests: EventReader<WebViewNewWindowRequested>,
+        openers: Query<
+            (
+                &WebViewPopups,
+                &Node,
+                Option<&WebViewTarget>,
+                Option<&WebViewNavigationPolicy>,
+                Option<&WebViewCodec>,
+                Option<&WebViewIpcLimits>,
+                Option<&WebViewIpcSticky>,
+                Option<&WebViewTrustedOrigins>,
+            ),
+            (With<IpcSender<T>>, With<IpcQueue<U>>),
+        >,
+    ) {
+        for WebViewNewWindowRequested { opener, url } in requests.read() {
+            let Ok((popups, node, target, policy, codec, limits, sticky, trusted)) =
+                openers.get(*opener)
+            else {
+                continue;
+            };
+            let size = popups.size.unwrap_or(node.size());
+
+            let mut popup = commands.spawn((
+                UiWebViewBundle::<T, U> {
+                    node_bundle: NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            left: Val::Px(popups.offset.x),
+                            top: Val::Px(popups.offset.y),
+                            width: Val::Px(size.x),
+                            height: Val::Px(size.y),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    location: WebViewLocation::Url(url.clone()),
+                    ..default()
+                },
+                WebViewPopup { opener: *opener },
+            ));
+            if let Some(target) = target {
+                popup.insert(*target);
+            }
+            if let Some(policy) = policy {
+                popup.insert(policy.clone());
+            }
+            if let Some(codec) = codec {
+                popup.insert(codec.clone());
+            }
+            if let Some(limits) = limits {
+                popup.insert(*limits);
+            }
+            if let Some(sticky) = sticky {
+                popup.insert(sticky.clone());
+            }
+            if let Some(trusted) = trusted {
+                popup.insert(trusted.clone());
+            }
+
+            // Parenting keeps the popup positioned relative to, and despawned with, its opener
+            let popup = popup.id();
+            commands.entity(*opener).add_child(popup);
+        }
+    }
+}
+
+/// The policy as seen by `wry`'s navigation callbacks, which run outside of the `World`
+#[derive(Default)]
+pub(crate) struct NavigationRules {
+    pub(crate) policy: Option<WebViewNavigationPolicy>,
+    /// URL of the webview's own `WebViewLocation`
+    pub(crate) home: Option<String>,
+}
+
+impl NavigationRules {
+    pub(crate) fn allows(&self, url: &str) -> bool {
+        let Some(policy) = &self.policy else {
+            return true;
+        };
+        url.starts_with("about:") || self.is_home(url) || policy.allowed.matches(url)
+    }
+
+    /// Whether `url` is on the origin of the webview's own location, e.g. a relative link of it
+    fn is_home(&self, url: &str) -> bool {
+        let Some(home) = &self.home else {
+            return false;
+        };
+        match url_pattern::origin(home) {
+            Some(origin) => url_pattern::origin(url) == Some(origin),
+            None => home == url,
+        }
+    }
+}
+
+#[derive(Component, Clone, Default, Deref)]
+pub(crate) struct NavigationGuard(pub(crate) Arc<RwLock<NavigationRules>>);
+
+impl NavigationGuard {
+    pub(crate) fn new(policy: Option<&WebViewNavigationPolicy>, home: Option<String>) -> Self {
+        Self(Arc::new(RwLock::new(NavigationRules {
+            policy: policy.cloned(),
+            home,
+        })))
+    }
+
+    pub(crate) fn allows(&self, url: &str) -> bool {
+        self.read().map(|x| x.allows(url)).unwrap_or(false)
+    }
+
+    pub(crate) fn set_home(&self, home: Option<String>) {
+        if let Ok(mut rules) = self.write() {
+            rules.home = home;
+        }
+    }
+}
+
+impl WebViewNavigationPlugin {
+    fn on_policy_change(
+        mut removed: RemovedComponents<WebViewNavigationPolicy>,
+        changed: Query<
+            (&WebViewNavigationPolicy, &NavigationGuard),
+            Changed<WebViewNavigationPolicy>,
+        >,
+        guards: Query<&NavigationGuard>,
+    ) {
+        for (policy, guard) in &changed {
+            if let Ok(mut rules) = guard.write() {
+                rules.policy = Some(policy.clone());
+            }
+        }
+        for guard in guards.iter_many(removed.read()) {
+            if let Ok(mut rules) = guard.write() {
+                rules.policy = None;
+            }
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use bevy::{render::view::VisibilitySystems, utils::HashMap, winit::WinitWindows};
+
 use crate::*;
 
 pub struct WebViewReactivityPlugin;
@@ -12,6 +14,10 @@ impl Plugin for WebViewReactivityPlugin {
                 Self::on_webview_redirect,
                 Self::on_window_resize,
             ),
+        )
+        .add_systems(
+            PostUpdate,
+            Self::on_webview_visibility.after(VisibilitySystems::VisibilityPropagate),
         );
     }
 }
@@ -53,30 +59,76 @@ impl WebViewReactivityPlugin {
 
     fn on_webview_redirect(
         registry: NonSendMut<WebViewRegistry>,
-        query: Query<
-            (&WebViewHandle, &WebViewLocation),
+        mut failed: EventWriter<WebViewFailed>,
+        mut query: Query<
+            (
+                Entity,
+                &WebViewHandle,
+                &mut WebViewState,
+                Ref<WebViewLocation>,
+                Option<&NavigationGuard>,
+                Option<&IpcChannel>,
+                Option<&InlineHtml>,
+            ),
             (With<WebViewMarker>, Changed<WebViewLocation>),
         >,
     ) {
-        for (handle, location) in query.iter() {
-            handle.map(|x| {
-                registry.get(x).map(|webview| match location {
-                    WebViewLocation::Url(url) => webview.load_url(url),
-                    WebViewLocation::Html(_html) => {
-                        // TODO Implement HTML loading past builder
-                    }
-                })
-            });
+        // Freshly added locations are loaded by the builder in `on_webview_spawn`
+        for (entity, handle, mut state, location, guard, channel, html) in query
+            .iter_mut()
+            .filter(|(_, _, _, x, _, _, _)| !x.is_added())
+        {
+            // Webviews that couldn't be created are built anew, at their new location
+            if matches!(*state, WebViewState::Failed(_)) {
+                *state = WebViewState::Pending;
+                continue;
+            }
+            let url = match location.url() {
+                Ok(url) => url,
+                Err(error) => {
+                    // The current page stays up, as if the location never changed
+                    warn!("Could not load new location of {entity:?}: {error}");
+                    failed.send(WebViewFailed { entity, error });
+                    continue;
+                }
+            };
+            if let Some(guard) = guard {
+                guard.set_home(Some(url.clone()));
+            }
+            if let Some(channel) = channel {
+                channel.set_home(origin::home(&location));
+            }
+            if let (WebViewLocation::Html(x), Some(html)) = (&*location, html) {
+                html.set(x);
+            }
+            // A new document, even for swapped HTML, so the page starts over like after a reload
+            if let Some(webview) = handle.and_then(|x| registry.get(x)) {
+                webview.load_url(&url);
+            }
         }
     }
 
     fn on_window_resize(
-        e: EventReader<WindowResized>,
+        mut e: EventReader<WindowResized>,
         registry: NonSendMut<WebViewRegistry>,
-        query: Query<(&WebViewHandle, &Node, &GlobalTransform), With<WebViewHandle>>,
+        primary_window: Query<Entity, With<PrimaryWindow>>,
+        query: Query<
+            (
+                &WebViewHandle,
+                &Node,
+                &GlobalTransform,
+                Option<&WebViewTarget>,
+            ),
+            With<WebViewMarker>,
+        >,
     ) {
-        if !e.is_empty() {
-            for (handle, size, position) in &query {
+        let resized = e.read().map(|x| x.window).collect::<Vec<_>>();
+        if !resized.is_empty() {
+            let primary_window = primary_window.get_single().ok();
+            for (handle, size, position, _) in query.iter().filter(|(_, _, _, target)| {
+                WebViewTarget::resolve(*target, primary_window)
+                    .is_some_and(|window| resized.contains(&window))
+            }) {
                 let size = size.size();
                 let final_position = (
                     (position.translation().x - size.x / 2.0) as i32,
@@ -89,4 +141,108 @@ impl WebViewReactivityPlugin {
             }
         }
     }
+
+    fn on_webview_visibility(
+        registry: NonSendMut<WebViewRegistry>,
+        mut shown: Local<HashMap<Entity, bool>>,
+        primary_window: Query<Entity, With<PrimaryWindow>>,
+        winit_windows: Option<NonSend<WinitWindows>>,
+        query: Query<
+            (
+                Entity,
+                &WebViewHandle,
+                &InheritedVisibility,
+                Option<&WebViewTarget>,
+            ),
+            With<WebViewMarker>,
+        >,
+        styles: Query<(&Style, Option<&Parent>)>,
+    ) {
+        shown.retain(|entity, _| query.contains(*entity));
+        for (entity, handle, inherited, target) in &query {
+            let Some(webview) = handle.and_then(|x| registry.get(x)) else {
+                continue;
+            };
+
+            // `Display::None` anywhere up the UI tree hides the node without touching `Visibility`
+            let displayed = std::iter::successors(Some(entity), |x| {
+                styles
+                    .get(*x)
+                    .ok()
+                    .and_then(|(_, parent)| parent.map(Parent::get))
+            })
+            .filter_map(|x| styles.get(x).ok())
+            .all(|(style, _)| style.display != Display::None);
+            let visible = inherited.get() && displayed;
+
+            let was_shown = shown.insert(entity, visible);
+            if was_shown != Some(visible) {
+                webview.set_visible(visible);
+
+                // Hand keyboard focus back to the game so input doesn't go to a hidden page. Bevy
+                // may still think its window is focused while the page has it, so setting
+                // `Window::focused` isn't enough. Webviews spawned hidden never had focus, and
+                // the window only takes it back from its own webviews, not from other apps.
+                if was_shown == Some(true) {
+                    if let Some(window) =
+                        WebViewTarget::resolve(target, primary_window.get_single().ok())
+                            .zip(winit_windows.as_ref())
+                            .and_then(|(x, winit_windows)| winit_windows.get_window(x))
+                            .filter(|x| {
+                                let focused = x.has_focus();
+                                #[cfg(target_os = "windows")]
+                                let focused = is_foreground(*x);
+                                focused
+                            })
+                    {
+                        window.focus_window();
+                        #[cfg(target_os = "macos")]
+                        make_first_responder(window);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether `window` is the one in front, taking keyboard input for itself or its webviews
+///
+/// A focused webview takes focus from the window's own view, so winit's `has_focus` is false then.
+#[cfg(target_os = "windows")]
+fn is_foreground(window: &impl raw_window_handle::HasRawWindowHandle) -> bool {
+    use raw_window_handle::RawWindowHandle;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetForegroundWindow() -> isize;
+    }
+
+    match window.raw_window_handle() {
+        RawWindowHandle::Win32(handle) => {
+            // SAFETY: takes no arguments and only reads which window is in front
+            let foreground = unsafe { GetForegroundWindow() };
+            foreground == handle.hwnd as isize
+        }
+        _ => false,
+    }
+}
+
+/// Moves keyboard focus from the window's webviews to the game's own view
+///
+/// The window stays key when a webview in it is hidden, so `focus_window` doesn't do this.
+#[cfg(target_os = "macos")]
+fn make_first_responder(window: &impl raw_window_handle::HasRawWindowHandle) {
+    use objc::{
+        msg_send,
+        runtime::{Object, BOOL},
+        sel, sel_impl,
+    };
+    use raw_window_handle::RawWindowHandle;
+
+    if let RawWindowHandle::AppKit(handle) = window.raw_window_handle() {
+        let ns_window = handle.ns_window as *mut Object;
+        let ns_view = handle.ns_view as *mut Object;
+        // SAFETY: both handles belong to a live window, and this runs on the main thread
+        let _: BOOL = unsafe { msg_send![ns_window, makeFirstResponder: ns_view] };
+    }
 }
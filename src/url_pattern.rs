@@ -0,0 +1,210 @@
+/// Origins (`https://example.com`) and URL patterns (`https://*.example.com/docs/*`) a URL may
+/// match, e.g. to be allowed by a `WebViewNavigationPolicy`
+///
+/// A `*` in the host matches one or more subdomains, or part of a single label; a `*` in the path
+/// matches any run of characters of the path. Patterns without a path match any path.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UrlPatterns(pub Vec<String>);
+
+impl UrlPatterns {
+    /// Adds an origin or URL pattern
+    pub fn with(mut self, pattern: impl Into<String>) -> Self {
+        self.0.push(pattern.into());
+        self
+    }
+
+    pub fn matches(&self, url: &str) -> bool {
+        self.0.iter().any(|pattern| matches(pattern, url))
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for UrlPatterns {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+/// The parts of a `scheme://host[:port]/path` URL that allowlists look at
+struct Url<'a> {
+    scheme: String,
+    host: String,
+    /// Only set when it isn't the default port of the scheme
+    port: Option<&'a str>,
+    /// Without query and fragment; `None` if the URL has no path at all
+    path: Option<&'a str>,
+}
+
+impl<'a> Url<'a> {
+    fn parse(url: &'a str) -> Option<Self> {
+        let (scheme, rest) = url.split_once("://")?;
+        let (authority, rest) = rest.split_at(rest.find(['/', '?', '#']).unwrap_or(rest.len()));
+        // Credentials are no part of where the URL leads
+        let authority = authority.rsplit_once('@').map_or(authority, |(_, x)| x);
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(ipv6) => {
+                let (host, after) = ipv6.split_once(']')?;
+                (&authority[..host.len() + 2], after.strip_prefix(':'))
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let path = &rest[..rest.find(['?', '#']).unwrap_or(rest.len())];
+
+        let scheme = scheme.to_ascii_lowercase();
+        let default_port = match scheme.as_str() {
+            "http" | "ws" => Some("80"),
+            "https" | "wss" => Some("443"),
+            _ => None,
+        };
+        Some(Self {
+            port: port.filter(|x| !x.is_empty() && Some(*x) != default_port),
+            scheme,
+            host: host.trim_end_matches('.').to_ascii_lowercase(),
+            path: Some(path).filter(|x| !x.is_empty()),
+        })
+    }
+}
+
+/// `scheme://host[:port]` of `url`, if it has one
+pub(crate) fn origin(url: &str) -> Option<String> {
+    let url = Url::parse(url)?;
+    Some(match url.port {
+        Some(port) => format!("{}://{}:{port}", url.scheme, url.host),
+        None => format!("{}://{}", url.scheme, url.host),
+    })
+}
+
+/// Whether `url` matches one pattern of `UrlPatterns`
+///
+/// A `*` stays within the part of the URL it's written in, so e.g. a query can't pose as a host.
+fn matches(pattern: &str, url: &str) -> bool {
+    let (Some(pattern), Some(url)) = (Url::parse(pattern), Url::parse(url)) else {
+        // e.g. `about:blank`, which has no host to speak of
+        return !pattern.contains("://") && !url.contains("://") && wildcard_match(pattern, url);
+    };
+    wildcard_match(&pattern.scheme, &url.scheme)
+        && host_matches(&pattern.host, &url.host)
+        && match (pattern.port, url.port) {
+            (Some("*"), _) => true,
+            (pattern, url) => pattern == url,
+        }
+        && match pattern.path {
+            Some(path) => wildcard_match(path, url.path.unwrap_or("/")),
+            None => true,
+        }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    let pattern = pattern.split('.').collect::<Vec<_>>();
+    let host = host.split('.').collect::<Vec<_>>();
+    let labels_match = |pattern: &[&str], host: &[&str]| {
+        pattern.len() == host.len() && pattern.iter().zip(host).all(|(p, h)| wildcard_match(p, h))
+    };
+    match pattern.split_first() {
+        Some((&"*", rest)) if !rest.is_empty() => {
+            host.len() > rest.len() && labels_match(rest, &host[host.len() - rest.len()..])
+        }
+        _ => labels_match(&pattern, &host),
+    }
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts = parts.collect::<Vec<_>>();
+    let Some(last) = parts.pop() else {
+        // No wildcard at all
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(x) => rest = &rest[x + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origins_match_any_path() {
+        assert!(matches("https://example.com", "https://example.com"));
+        assert!(matches(
+            "https://example.com",
+            "https://example.com/a/b?c#d"
+        ));
+        assert!(matches("https://example.com", "HTTPS://Example.COM:443/"));
+        assert!(!matches("https://example.com", "http://example.com/"));
+        assert!(!matches("https://example.com", "https://example.com:8443/"));
+        assert!(!matches(
+            "https://example.com",
+            "https://example.com.evil.com/"
+        ));
+    }
+
+    #[test]
+    fn host_wildcards_stay_in_the_host() {
+        let pattern = "https://*.example.com";
+        assert!(matches(pattern, "https://a.example.com/"));
+        assert!(matches(pattern, "https://a.b.example.com/x"));
+        assert!(!matches(pattern, "https://example.com/"));
+        assert!(!matches(pattern, "https://evilexample.com/"));
+        assert!(!matches(pattern, "https://evil.com/x.example.com"));
+        assert!(!matches(pattern, "https://evil.com/?q=.example.com"));
+        assert!(!matches(pattern, "https://evil.com/#.example.com"));
+        assert!(!matches(pattern, "https://a.example.com@evil.com/"));
+        assert!(!matches(pattern, "https://evil.com:1.example.com/"));
+    }
+
+    #[test]
+    fn path_wildcards_stay_in_the_path() {
+        let pattern = "https://*.example.com/app/*";
+        assert!(matches(pattern, "https://a.example.com/app/"));
+        assert!(matches(pattern, "https://a.example.com/app/x/y?z=1"));
+        assert!(!matches(pattern, "https://a.example.com/other/app/"));
+        assert!(!matches(pattern, "https://a.example.com/?q=/app/"));
+        assert!(!matches(pattern, "https://evil.com/?q=.example.com/app/"));
+        assert!(!matches(pattern, "https://evil.com/x.example.com/app/"));
+    }
+
+    #[test]
+    fn ports() {
+        assert!(matches("http://localhost:*", "http://localhost:8080/"));
+        assert!(matches("http://localhost:8080", "http://localhost:8080/x"));
+        assert!(!matches("http://localhost:8080", "http://localhost:8081/"));
+        assert!(!matches("http://localhost", "http://localhost:8080/"));
+    }
+
+    #[test]
+    fn urls_without_host() {
+        assert!(matches("about:blank", "about:blank"));
+        assert!(matches("about:*", "about:srcdoc"));
+        assert!(!matches("about:*", "https://evil.com/about:blank"));
+        assert!(!matches("https://*", "about:blank"));
+    }
+
+    #[test]
+    fn origins() {
+        assert_eq!(
+            origin("https://user@Example.com:443/a?b").as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            origin("http://[::1]:8080/").as_deref(),
+            Some("http://[::1]:8080")
+        );
+        assert_eq!(origin("about:blank"), None);
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+
+use bevy_wry_webview::ipc::typescript::{declarations, TypeScript};
+use serde::{Deserialize, Serialize};
+
+/// The declarations of the named types `T` refers to, without the client typings
+fn declared<T: TypeScript>() -> String {
+    let out = declarations::<T, ()>();
+    let out = out
+        .strip_prefix("// Generated by bevy_wry_webview; do not edit\n\n")
+        .unwrap();
+    out[..out
+        .find("\n/** Messages Bevy sends to the page */")
+        .unwrap()]
+        .to_owned()
+}
+
+#[test]
+fn structs() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Player {
+        name: String,
+        health: f32,
+        tags: Vec<String>,
+        stats: HashMap<String, u32>,
+        team: Option<u8>,
+    }
+
+    assert_eq!(
+        declared::<Player>(),
+        "export type Player = { name: string; health: number; tags: string[]; \
+         stats: Record<string, number>; team: number | null };\n"
+    );
+}
+
+#[test]
+fn renamed_fields() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    #[serde(rename_all = "camelCase")]
+    struct Settings {
+        master_volume: f32,
+        #[serde(rename = "fullscreen")]
+        is_fullscreen: bool,
+        #[serde(rename = "key binding")]
+        key_binding: String,
+        #[serde(rename(serialize = "ser", deserialize = "de"))]
+        both: u8,
+    }
+
+    assert_eq!(
+        declared::<Settings>(),
+        "export type Settings = { masterVolume: number; fullscreen: boolean; \
+         \"key binding\": string; ser: number };\n"
+    );
+}
+
+#[test]
+fn skipped_and_default_fields() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Config {
+        #[serde(skip)]
+        _cache: Vec<u8>,
+        #[serde(default)]
+        retries: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        proxy: Option<String>,
+        #[serde(default = "default_port")]
+        port: u16,
+        host: String,
+    }
+
+    fn default_port() -> u16 {
+        80
+    }
+
+    assert_eq!(
+        declared::<Config>(),
+        "export type Config = { retries?: number; proxy?: string | null; port?: number; \
+         host: string };\n"
+    );
+}
+
+#[test]
+fn fields_skipped_one_way() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    enum Status {
+        Online,
+        #[serde(skip_serializing)]
+        Away,
+    }
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Account {
+        name: String,
+        #[serde(skip_serializing)]
+        password: String,
+        #[serde(skip_deserializing)]
+        id: u32,
+        status: Status,
+    }
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Tag(String, #[serde(skip_deserializing)] u8);
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Profile {
+        account: Account,
+        tags: Vec<Tag>,
+        bio: String,
+    }
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Color(u8, u8, u8);
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Both {
+        profile: Profile,
+        color: Color,
+    }
+
+    // Bevy sends `T` and receives `U`; types that look the same both ways are declared once
+    let out = declarations::<Both, Both>();
+    assert!(out.contains(
+        "export type Status = \"Online\";\n\
+         export type Account = { name: string; id: number; status: Status };\n\
+         export type Tag = [string, number];\n\
+         export type Profile = { account: Account; tags: Tag[]; bio: string };\n\
+         export type Color = [number, number, number];\n\
+         export type Both = { profile: Profile; color: Color };\n\
+         export type StatusOutgoing = \"Online\" | \"Away\";\n\
+         export type AccountOutgoing = { name: string; password: string; status: StatusOutgoing };\n\
+         export type TagOutgoing = [string];\n\
+         export type ProfileOutgoing = { account: AccountOutgoing; tags: TagOutgoing[]; bio: string };\n\
+         export type BothOutgoing = { profile: ProfileOutgoing; color: Color };\n"
+    ));
+    assert!(out.contains("export type IncomingMessage = Both;\n"));
+    assert!(out.contains("export type OutgoingMessage = BothOutgoing;\n"));
+}
+
+#[test]
+fn flattened_fields() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Marker {
+        label: String,
+        #[serde(flatten)]
+        position: Position,
+    }
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct OnlyFlattened {
+        #[serde(flatten)]
+        position: Position,
+    }
+
+    assert_eq!(
+        declared::<Marker>(),
+        "export type Position = { x: number; y: number };\n\
+         export type Marker = { label: string } & Position;\n"
+    );
+    assert_eq!(
+        declared::<OnlyFlattened>(),
+        "export type Position = { x: number; y: number };\n\
+         export type OnlyFlattened = Position;\n"
+    );
+}
+
+#[test]
+fn tuple_unit_and_transparent_structs() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Id(u64);
+
+    #[allow(dead_code)]
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Pair(String, #[serde(skip)] u8, bool);
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Ping;
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    #[serde(transparent)]
+    struct Name {
+        #[serde(skip)]
+        _len: usize,
+        inner: String,
+    }
+
+    assert_eq!(declared::<Id>(), "export type Id = number;\n");
+    assert_eq!(
+        declared::<Pair>(),
+        "export type Pair = [string, boolean];\n"
+    );
+    assert_eq!(declared::<Ping>(), "export type Ping = null;\n");
+    assert_eq!(declared::<Name>(), "export type Name = string;\n");
+}
+
+#[test]
+fn tagged_struct() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    #[serde(tag = "type", rename = "hello")]
+    struct Hello {
+        from: String,
+    }
+
+    assert_eq!(
+        declared::<Hello>(),
+        "export type Hello = { type: \"hello\"; from: string };\n"
+    );
+}
+
+#[test]
+fn externally_tagged_enums() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    #[serde(rename_all = "snake_case")]
+    enum Command {
+        Quit,
+        MoveTo {
+            x: f32,
+            y: f32,
+        },
+        Say(String),
+        Swap(u8, u8),
+        #[serde(rename = "reset")]
+        ResetAll,
+        #[serde(skip)]
+        _Internal,
+    }
+
+    assert_eq!(
+        declared::<Command>(),
+        "export type Command = \"quit\" | { move_to: { x: number; y: number } } | \
+         { say: string } | { swap: [number, number] } | \"reset\";\n"
+    );
+}
+
+#[test]
+fn internally_tagged_enums() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Score {
+        points: u32,
+    }
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    #[serde(tag = "type", rename_all = "camelCase")]
+    enum Event {
+        GameOver,
+        #[serde(rename_all = "camelCase")]
+        PlayerJoined {
+            player_name: String,
+            #[serde(default)]
+            team_id: u8,
+        },
+        NewScore(Score),
+    }
+
+    assert_eq!(
+        declared::<Event>(),
+        "export type Score = { points: number };\n\
+         export type Event = { type: \"gameOver\" } | \
+         { type: \"playerJoined\"; playerName: string; teamId?: number } | \
+         { type: \"newScore\" } & Score;\n"
+    );
+}
+
+#[test]
+fn adjacently_tagged_enums() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    #[serde(tag = "kind", content = "data")]
+    enum Reply {
+        Empty,
+        Text(String),
+        Pair(u8, u8),
+        Error { code: u16 },
+    }
+
+    assert_eq!(
+        declared::<Reply>(),
+        "export type Reply = { kind: \"Empty\" } | { kind: \"Text\"; data: string } | \
+         { kind: \"Pair\"; data: [number, number] } | \
+         { kind: \"Error\"; data: { code: number } };\n"
+    );
+}
+
+#[test]
+fn untagged_enums() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    #[serde(untagged)]
+    enum Value {
+        Nothing,
+        Number(f64),
+        Point(f32, f32),
+        Named { name: String },
+    }
+
+    assert_eq!(
+        declared::<Value>(),
+        "export type Value = null | number | [number, number] | { name: string };\n"
+    );
+}
+
+#[test]
+fn recursive_and_shared_types() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Tree {
+        value: i32,
+        children: Vec<Tree>,
+        parent: Option<Box<Tree>>,
+    }
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct Forest {
+        first: Tree,
+        rest: Vec<Tree>,
+    }
+
+    assert_eq!(
+        declared::<Forest>(),
+        "export type Tree = { value: number; children: Tree[]; parent: Tree | null };\n\
+         export type Forest = { first: Tree; rest: Tree[] };\n"
+    );
+}
+
+#[test]
+fn declarations_type_the_client() {
+    #[derive(Serialize, Deserialize, TypeScript)]
+    #[serde(tag = "type")]
+    enum ToPage {
+        Score { points: u32 },
+    }
+
+    #[derive(Serialize, Deserialize, TypeScript)]
+    struct FromPage {
+        clicked: bool,
+    }
+
+    let out = declarations::<ToPage, FromPage>();
+    assert!(out.starts_with(
+        "// Generated by bevy_wry_webview; do not edit\n\n\
+         export type ToPage = { type: \"Score\"; points: number };\n\
+         export type FromPage = { clicked: boolean };\n"
+    ));
+    assert!(out.contains("export type IncomingMessage = ToPage;\n"));
+    assert!(out.contains("export type OutgoingMessage = FromPage;\n"));
+    assert!(out.contains("send(msg: OutgoingMessage): Promise<void>;"));
+}